@@ -7,20 +7,92 @@ function getPreviousDay(date = new Date()) {
 }
 
 const datasetExchange = document.getElementById("dataset-exchange");
+const datasetMarket = document.getElementById("dataset-market");
+const datasetType = document.getElementById("dataset-type");
+const datasetInterval = document.getElementById("dataset-interval");
 const datasetPair = document.getElementById("dataset-pair");
+const datasetPairs = document.getElementById("dataset-pairs");
 const datasetDate = document.getElementById("dataset-date");
 datasetDate.max = getPreviousDay().toISOString().split('T')[0];
 datasetDate.value = datasetDate.max;
+const datasetDateTo = document.getElementById("dataset-date-to");
+datasetDateTo.max = datasetDate.max;
 const chartElement = document.getElementById("chart");
 let chart = null;
 
 let currentDataset = null;
+let exchanges = {};
+
+async function loadExchanges() {
+    let response = await fetch(apiHost + "/exchanges");
+    if (response.status / 100 != 2) {
+        return;
+    }
+    (await response.json()).forEach(exchange => {
+        exchanges[exchange.name] = exchange;
+        if (![...datasetExchange.options].some(option => option.value === exchange.name)) {
+            datasetExchange.add(new Option(exchange.name, exchange.name));
+        }
+    });
+}
+loadExchanges();
+
+// podpowiedzi par z listingu gieldy, pusta lista gdy gielda nie umie listowac
+async function loadSymbols() {
+    let response = await fetch(apiHost + "/symbols/" + encodeURIComponent(datasetExchange.value)
+        + "?market=" + encodeURIComponent(datasetMarket.value));
+    let symbols = response.status / 100 == 2 ? await response.json() : [];
+    datasetPairs.replaceChildren(...symbols.map(symbol => new Option(symbol)));
+}
+loadSymbols();
+[datasetExchange, datasetMarket].forEach(e => e.addEventListener("change", loadSymbols));
+
+const defaultMinDate = datasetDate.min;
+const defaultMaxDate = datasetDate.max;
+// ostrzegamy przed wczytaniem wiekszej ilosci archiwow niz tyle
+const largeArchiveBytes = 500 * 1024 * 1024;
+let archiveSizes = {};
+
+async function loadAvailability() {
+    let response = await fetch(apiHost + "/availability/" + encodeURIComponent(datasetExchange.value) + "/"
+        + encodeURIComponent(datasetPair.value) + "?market=" + encodeURIComponent(datasetMarket.value)
+        + (datasetType.value !== "klines" ? "&dataset=" + encodeURIComponent(datasetType.value) : ""));
+    let days = response.status / 100 == 2 ? await response.json() : [];
+    archiveSizes = Object.fromEntries(days.map(day => [day.date, day.size]));
+    let min = days.length > 0 ? days[0].date : defaultMinDate;
+    let max = days.length > 0 ? days[days.length - 1].date : defaultMaxDate;
+    [datasetDate, datasetDateTo].forEach(e => {
+        e.min = min;
+        e.max = max;
+    });
+}
+loadAvailability();
+[datasetExchange, datasetMarket, datasetType, datasetPair].forEach(e => e.addEventListener("change", loadAvailability));
+
+function archivesSize(date, dateTo) {
+    let size = 0;
+    for (let day = new Date(date); day <= new Date(dateTo || date); day.setUTCDate(day.getUTCDate() + 1)) {
+        size += archiveSizes[day.toISOString().split('T')[0]] || 0;
+    }
+    return size;
+}
 
 function submit() {
-    lookup(datasetExchange.value, datasetPair.value, datasetDate.value);
+    if (datasetType.value === "klines") {
+        lookupCandles(datasetExchange.value, datasetMarket.value, datasetInterval.value, datasetPair.value,
+            datasetDate.value);
+        return;
+    }
+    let size = archivesSize(datasetDate.value, datasetDateTo.value);
+    if (size > largeArchiveBytes
+        && !confirm("Archiwa maja " + Math.round(size / 1024 / 1024) + " MB, na pewno wczytac?")) {
+        return;
+    }
+    lookup(datasetExchange.value, datasetMarket.value, datasetType.value, datasetPair.value, datasetDate.value,
+        datasetDateTo.value);
 }
 document.getElementById("dataset-submit").onclick = submit;
-[datasetPair, datasetDate].forEach(e => e.addEventListener("keyup", function(event) {
+[datasetPair, datasetDate, datasetDateTo].forEach(e => e.addEventListener("keyup", function(event) {
     if (event.key === "Enter") {
         submit();
     }
@@ -36,6 +108,8 @@ function filterDataset(dataset) {
     let buyDataset = [];
     let sellDataset = [];
     dataset.forEach(entry => {
+        // ceny przychodza jako stringi zeby nie gubic precyzji
+        entry[1] = Number(entry[1]);
         switch (entry[2]) {
             case "buy":
                 buyDataset.push(entry);
@@ -51,16 +125,35 @@ function filterDataset(dataset) {
     return [buyDataset, sellDataset];
 }
 
-async function lookup(exchange, coinPair, date) {
+async function lookup(exchange, market, type, coinPair, date, dateTo) {
     chartElement.innerHTML = "<h1>loding</h1>";
+    const info = exchanges[exchange];
+    if (info && !info.datasets.includes(type)) {
+        type = info.datasets[0];
+    }
+    if (info && !info.markets.includes(market)) {
+        market = info.markets[0];
+    }
+    // olx ma tylko trades, rynek wynika z pary (BTC-USDT-SWAP, BTC-USD-221230)
+    if (exchange === "olx") {
+        type = "trades";
+        if (market !== "spot" && coinPair.split("-").length > 2) {
+            market = "";
+        }
+    }
     let response = await fetch(apiHost + "/dataset/" + encodeURIComponent(exchange) + "/"
-        + encodeURIComponent(coinPair) + "/" + encodeURIComponent(date));
+        + encodeURIComponent(coinPair) + "/" + encodeURIComponent(date)
+        + "?dataset=" + encodeURIComponent(type) + (market ? "&market=" + encodeURIComponent(market) : "")
+        + (dateTo ? "&to=" + encodeURIComponent(dateTo) : ""));
     if (response.status / 100 != 2) {
         let errMessage = await response.json().then(j => j.error);
         chartElement.innerText = "blą∂: " + errMessage;
         return;
     }
     currentDataset = await response.json();
+    if (hasReadError(currentDataset)) {
+        return;
+    }
     // po dacie sortowanie bo ten olx zajebany to fikolka jakiegos robi Xd
     currentDataset.sort(function(a, b) {
       return a[0] - b[0];
@@ -131,6 +224,75 @@ async function lookup(exchange, coinPair, date) {
     });
 }
 
+// blad po wyslaniu odpowiedzi przychodzi jako ostatni element {err}
+function hasReadError(rows) {
+    let last = rows[rows.length - 1];
+    if (last && last.err) {
+        chartElement.innerText = "blą∂: " + last.err;
+        return true;
+    }
+    return false;
+}
+
+async function lookupCandles(exchange, market, interval, coinPair, date) {
+    chartElement.innerHTML = "<h1>loding</h1>";
+    let response = await fetch(apiHost + "/candles/" + encodeURIComponent(exchange) + "/"
+        + encodeURIComponent(coinPair) + "/" + encodeURIComponent(date)
+        + "?market=" + encodeURIComponent(market) + "&interval=" + encodeURIComponent(interval));
+    if (response.status / 100 != 2) {
+        let errMessage = await response.json().then(j => j.error);
+        chartElement.innerText = "blą∂: " + errMessage;
+        return;
+    }
+    let candles = await response.json();
+    if (hasReadError(candles)) {
+        return;
+    }
+    let ohlc = candles.map(candle => [candle[0], ...candle.slice(1, 5).map(Number)]);
+    let volume = candles.map(candle => [candle[0], Number(candle[5])]);
+
+    chart = Highcharts.stockChart('chart', {
+        title: {
+            text: coinPair + " " + interval,
+            align: 'left'
+        },
+        rangeSelector: {
+            buttons: [
+                { type: 'hour', count: 1, text: '1h' },
+                { type: 'hour', count: 6, text: '6h' },
+                { type: 'day', count: 1, text: '1d' },
+                { type: 'week', count: 1, text: '1w' },
+                { type: 'all', text: 'All' },
+            ],
+            inputEnabled: false,
+            selected: 4,
+        },
+        yAxis: [
+            { height: '75%' },
+            { top: '75%', height: '25%', offset: 0 },
+        ],
+        series: [
+            {
+                type: 'candlestick',
+                name: coinPair,
+                data: ohlc,
+                dataGrouping: {
+                    enabled: false
+                }
+            },
+            {
+                type: 'column',
+                name: 'volume',
+                data: volume,
+                yAxis: 1,
+                dataGrouping: {
+                    enabled: false
+                }
+            },
+        ],
+    });
+}
+
 Highcharts.theme = {
     colors: ['#8087E8', '#A3EDBA', '#F19E53', '#6699A1',
         '#E1D369', '#87B4E7', '#DA6D85', '#BBBAC5'],
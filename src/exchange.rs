@@ -1,3 +1,17 @@
 pub mod trade_reader;
+pub mod archive_cache;
 pub mod binance;
+pub mod bybit;
+pub mod checksum;
+pub mod download;
+pub mod http_pool;
+pub mod kraken;
+pub mod local_mirror;
 pub mod olx;
+pub mod proxy;
+pub mod registry;
+pub mod seekable_zip;
+pub mod symbol_cache;
+#[cfg(test)]
+pub mod test_server;
+pub mod upstream;
@@ -0,0 +1,381 @@
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::SystemTime;
+use tokio::io;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter, ReadBuf};
+
+const DATA_EXTENSION: &str = "archive";
+const META_EXTENSION: &str = "meta";
+const PART_EXTENSION: &str = "part";
+
+/// Archives downloaded before, stored under `dir` by the hash of their URL
+/// along with the ETag they were served with. Least recently used archives
+/// are removed once the total size exceeds `max_bytes`. Files are never
+/// touched while `entries` is locked.
+pub struct ArchiveCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    next_part: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    etag: String,
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl ArchiveCache {
+    /// Opens the cache in `dir`, picking up archives stored by previous runs.
+    /// Meant to run before serving, it blocks on the file system.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut entries = HashMap::new();
+        for file in fs::read_dir(&dir)? {
+            let path = file?.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some(PART_EXTENSION) => fs::remove_file(&path)?,
+                Some(META_EXTENSION) => {
+                    if let Some((key, entry)) = read_entry(&path) {
+                        entries.insert(key, entry);
+                    }
+                }
+                _ => {}
+            }
+        }
+        info!(
+            "Archive cache in {} has {} archives",
+            dir.display(),
+            entries.len()
+        );
+        let cache = Self {
+            dir,
+            max_bytes,
+            entries: Mutex::new(entries),
+            next_part: AtomicU64::new(0),
+        };
+        let evicted = cache.evict(&mut cache.entries.lock().unwrap());
+        for key in evicted {
+            for path in cache.entry_paths(&key) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(cache)
+    }
+
+    /// ETag of the cached copy of `url`, to revalidate it with.
+    pub fn etag(&self, url: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(&cache_key(url)).map(|entry| entry.etag.clone())
+    }
+
+    /// Opens the cached copy of `url` and marks it as recently used. The file
+    /// is opened before it is marked, so an archive evicted in the meantime is
+    /// a miss, and once open it stays readable even if it is evicted later.
+    pub async fn open_cached(&self, url: &str) -> io::Result<Option<tokio::fs::File>> {
+        let key = cache_key(url);
+        if !self.entries.lock().unwrap().contains_key(&key) {
+            return Ok(None);
+        }
+        let path = self.path(&key, DATA_EXTENSION);
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        match self.entries.lock().unwrap().get_mut(&key) {
+            Some(entry) => entry.last_used = SystemTime::now(),
+            None => return Ok(Some(file)),
+        }
+        // keeps the order of use across restarts, see `read_entry`
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = fs::File::options()
+                .append(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+            {
+                warn!("Could not touch {}: {}", path.display(), err);
+            }
+        });
+        Ok(Some(file))
+    }
+
+    /// Copies everything read from `reader` into the cache as `url`. The copy
+    /// is only kept when `reader` is read to the end without errors.
+    pub async fn tee<R>(self: &Arc<Self>, reader: R, url: &str, etag: String) -> TeeReader<R> {
+        let key = cache_key(url);
+        let part = self.path(
+            &format!("{}.{}", key, self.next_part.fetch_add(1, Ordering::Relaxed)),
+            PART_EXTENSION,
+        );
+        let writer = match tokio::fs::File::create(&part).await {
+            Ok(file) => Some(CacheWriter {
+                cache: self.clone(),
+                key,
+                url: url.to_string(),
+                etag,
+                part,
+                file: BufWriter::new(file),
+                pending: Vec::new(),
+                size: 0,
+            }),
+            Err(err) => {
+                warn!("Could not cache {}: {}", url, err);
+                None
+            }
+        };
+        TeeReader {
+            inner: reader,
+            writer,
+            committing: None,
+        }
+    }
+
+    fn path(&self, key: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, extension))
+    }
+
+    fn entry_paths(&self, key: &str) -> [PathBuf; 2] {
+        [
+            self.path(key, META_EXTENSION),
+            self.path(key, DATA_EXTENSION),
+        ]
+    }
+
+    /// Removes least recently used entries above `max_bytes`, their files are
+    /// left to the caller to remove once `entries` is unlocked.
+    fn evict(&self, entries: &mut HashMap<String, CacheEntry>) -> Vec<String> {
+        let mut evicted = Vec::new();
+        let mut total: u64 = entries.values().map(|entry| entry.size).sum();
+        while total > self.max_bytes {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(key) = oldest else {
+                break;
+            };
+            let entry = entries.remove(&key).expect("Key was just found");
+            total -= entry.size;
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+fn cache_key(url: &str) -> String {
+    hex::encode(Sha256::digest(url.as_bytes()))
+}
+
+/// Reads `{key}.meta` written by [`CacheWriter::commit`], last use is the
+/// modification time of the archive itself.
+fn read_entry(meta: &Path) -> Option<(String, CacheEntry)> {
+    let key = meta.file_stem()?.to_str()?.to_string();
+    let content = fs::read_to_string(meta).ok()?;
+    let etag = content.lines().next()?.to_string();
+    let data = fs::metadata(meta.with_extension(DATA_EXTENSION)).ok()?;
+    let entry = CacheEntry {
+        etag,
+        size: data.len(),
+        last_used: data.modified().ok()?,
+    };
+    Some((key, entry))
+}
+
+struct CacheWriter {
+    cache: Arc<ArchiveCache>,
+    key: String,
+    url: String,
+    etag: String,
+    /// Emptied once the part is renamed to the archive.
+    part: PathBuf,
+    file: BufWriter<tokio::fs::File>,
+    /// Bytes already read but not yet written.
+    pending: Vec<u8>,
+    size: u64,
+}
+
+impl CacheWriter {
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let written = ready!(Pin::new(&mut self.file).poll_write(cx, &self.pending))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..written);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Moves the part in place of the archive and records it.
+    async fn commit(mut self) -> io::Result<()> {
+        self.file.write_all(&self.pending).await?;
+        self.file.flush().await?;
+        let cache = self.cache.clone();
+        tokio::fs::rename(&self.part, cache.path(&self.key, DATA_EXTENSION)).await?;
+        self.part = PathBuf::new();
+        tokio::fs::write(
+            cache.path(&self.key, META_EXTENSION),
+            format!("{}\n{}\n", self.etag, self.url),
+        )
+        .await?;
+        let evicted = {
+            let mut entries = cache.entries.lock().unwrap();
+            entries.insert(
+                self.key.clone(),
+                CacheEntry {
+                    etag: self.etag.clone(),
+                    size: self.size,
+                    last_used: SystemTime::now(),
+                },
+            );
+            cache.evict(&mut entries)
+        };
+        debug!("Cached {} ({} bytes)", self.url, self.size);
+        for key in evicted {
+            for path in cache.entry_paths(&key) {
+                if let Err(err) = tokio::fs::remove_file(path).await {
+                    warn!("Could not evict {} from archive cache: {}", key, err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        if self.part.as_os_str().is_empty() {
+            return;
+        }
+        let part = std::mem::take(&mut self.part);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(tokio::fs::remove_file(part));
+            }
+            Err(_) => {
+                let _ = fs::remove_file(part);
+            }
+        }
+    }
+}
+
+/// Writes what is read into the cache, each read is written before the next
+/// one, and the archive is committed before the end is reported.
+pub struct TeeReader<R> {
+    inner: R,
+    writer: Option<CacheWriter>,
+    committing: Option<BoxFuture<'static, io::Result<()>>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TeeReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if let Some(committing) = &mut this.committing {
+            if let Err(err) = ready!(committing.as_mut().poll(cx)) {
+                warn!("Could not write archive cache: {}", err);
+            }
+            this.committing = None;
+            return Poll::Ready(Ok(()));
+        }
+        if let Some(writer) = &mut this.writer {
+            if let Err(err) = ready!(writer.poll_write_pending(cx)) {
+                warn!("Could not write archive cache: {}", err);
+                this.writer = None;
+            }
+        }
+        let filled_before = buf.filled().len();
+        let result = ready!(Pin::new(&mut this.inner).poll_read(cx, buf));
+        match &result {
+            Ok(()) => {
+                let read = &buf.filled()[filled_before..];
+                match (this.writer.take(), read.is_empty()) {
+                    (Some(writer), true) => {
+                        this.committing = Some(Box::pin(writer.commit()));
+                        return self.poll_read(cx, buf);
+                    }
+                    (Some(mut writer), false) => {
+                        writer.pending.extend_from_slice(read);
+                        writer.size += read.len() as u64;
+                        this.writer = Some(writer);
+                    }
+                    (None, _) => {}
+                }
+            }
+            Err(_) => this.writer = None,
+        }
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    /// Directory removed when dropped, also when the test fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("archive-cache-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn store(cache: &Arc<ArchiveCache>, url: &str, content: &[u8]) {
+        let mut tee = cache.tee(content, url, format!("\"{}\"", url)).await;
+        tokio::io::copy(&mut tee, &mut tokio::io::sink())
+            .await
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn evict_least_recently_used() {
+        let dir = TempDir::new("evict");
+        let cache = Arc::new(ArchiveCache::open(&dir.0, 10).unwrap());
+        store(&cache, "a", b"aaaa").await;
+        store(&cache, "b", b"bbbb").await;
+        assert!(cache.open_cached("a").await.unwrap().is_some());
+        store(&cache, "c", b"cccc").await;
+
+        assert_eq!(cache.etag("a"), Some("\"a\"".to_string()));
+        assert_eq!(cache.etag("b"), None);
+        let mut cached = String::new();
+        cache
+            .open_cached("c")
+            .await
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut cached)
+            .await
+            .unwrap();
+        assert_eq!(cached, "cccc");
+
+        let reopened = ArchiveCache::open(&dir.0, 10).unwrap();
+        assert_eq!(reopened.etag("c"), Some("\"c\"".to_string()));
+        assert_eq!(reopened.etag("b"), None);
+    }
+}
@@ -1,2 +1,152 @@
 pub mod ws;
 pub mod data;
+
+use crate::exchange::registry::{
+    archive_trades, ArchiveDay, ArchiveExchange, DatasetRequest, Exchange, ExchangeError,
+};
+use crate::exchange::trade_reader::{
+    ArchiveClient, ArchivePeriod, ArchiveUrl, Candle, Contract, CreateStream, DatasetType,
+    KlineInterval, Market, TradePair, TradeParser, TradeReader,
+};
+use futures::future::BoxFuture;
+
+pub struct Binance;
+
+impl Binance {
+    fn market(&self, request: &DatasetRequest) -> Market {
+        request.market.unwrap_or(self.markets()[0])
+    }
+}
+
+impl Exchange for Binance {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn markets(&self) -> &'static [Market] {
+        &[Market::UsdMFutures, Market::CoinMFutures, Market::Spot]
+    }
+
+    fn dataset_types(&self) -> &'static [DatasetType] {
+        &[DatasetType::Trades, DatasetType::AggTrades]
+    }
+
+    fn sample_trade_every(&self) -> usize {
+        50
+    }
+
+    fn supports_candles(&self) -> bool {
+        true
+    }
+
+    fn trades<'a>(
+        &'a self,
+        client: &'a ArchiveClient,
+        request: &'a DatasetRequest,
+        sample_every_n_trade: usize,
+    ) -> BoxFuture<'a, Result<CreateStream, ExchangeError>> {
+        Box::pin(archive_trades(self, client, request, sample_every_n_trade))
+    }
+
+    fn candles<'a>(
+        &'a self,
+        client: &'a ArchiveClient,
+        market: Market,
+        interval: KlineInterval,
+        trade_pair: &'a TradePair,
+        period: ArchivePeriod,
+    ) -> BoxFuture<'a, Result<CreateStream<Candle>, ExchangeError>> {
+        Box::pin(async move {
+            if market == Market::Spot && trade_pair.contract.is_some() {
+                return Err(ExchangeError::SpotContract);
+            }
+            let reader =
+                data::aws_candle_reader(client, market, interval, trade_pair, period).await?;
+            Ok(reader.stream(1).await)
+        })
+    }
+
+    fn symbols(
+        &self,
+        client: &ArchiveClient,
+        market: Market,
+    ) -> BoxFuture<'static, Result<Vec<TradePair>, ExchangeError>> {
+        let client = client.clone();
+        Box::pin(async move {
+            data::list_symbols(&client, market)
+                .await
+                .map_err(ExchangeError::from)
+        })
+    }
+
+    fn availability(
+        &self,
+        client: &ArchiveClient,
+        market: Market,
+        dataset_type: DatasetType,
+        trade_pair: TradePair,
+    ) -> BoxFuture<'static, Result<Vec<ArchiveDay>, ExchangeError>> {
+        let client = client.clone();
+        Box::pin(async move {
+            let days = data::list_archive_days(&client, market, dataset_type, &trade_pair)
+                .await
+                .map_err(ExchangeError::from)?;
+            Ok(days
+                .into_iter()
+                .map(|(date, size)| ArchiveDay { date, size })
+                .collect())
+        })
+    }
+}
+
+impl ArchiveExchange for Binance {
+    /// Monthly archives are published some days after the month ended, their
+    /// days are read until then.
+    fn archive_urls(&self, request: &DatasetRequest) -> Result<Vec<ArchiveUrl>, ExchangeError> {
+        let market = self.market(request);
+        if market == Market::Spot && request.trade_pair.contract.is_some() {
+            return Err(ExchangeError::SpotContract);
+        }
+        if market == Market::CoinMFutures
+            && data::coin_m_contract_size(&request.trade_pair).is_none()
+        {
+            return Err(ExchangeError::UnsupportedContract(
+                request.trade_pair.contract.unwrap_or(Contract::Perpetual),
+                request.trade_pair.to_string(),
+            ));
+        }
+        let url = |period| {
+            data::trades_archive_url(market, request.dataset_type, &request.trade_pair, period)
+        };
+        Ok(data::archive_periods(request.from, request.to)
+            .into_iter()
+            .map(|period| ArchiveUrl {
+                url: url(period),
+                fallback: match period {
+                    ArchivePeriod::Daily(_) => Vec::new(),
+                    ArchivePeriod::Monthly { .. } => period
+                        .days()
+                        .into_iter()
+                        .map(|date| url(ArchivePeriod::Daily(date)))
+                        .collect(),
+                },
+            })
+            .collect())
+    }
+
+    fn parser(&self, request: &DatasetRequest) -> TradeParser {
+        data::trade_parser(
+            self.market(request),
+            request.dataset_type,
+            &request.trade_pair,
+        )
+    }
+
+    fn archive_entries(&self) -> Option<&'static str> {
+        Some("*.csv")
+    }
+
+    fn publishes_checksums(&self) -> bool {
+        true
+    }
+}
@@ -1,34 +1,237 @@
+use crate::exchange::registry::ExchangeError;
 use crate::exchange::trade_reader::{
-    http_zip_lines_reader, HttpZipReaderError, ParseTradeError, Trade, TradePair, TradeSide,
-    ZipCsvTradeReader,
+    http_zip_lines_reader, ArchiveClient, ArchivePeriod, Candle, Contract, CsvTradeReader,
+    DatasetType, HttpZipReaderError, KlineInterval, Market, ParseTradeError, Timestamp, Trade,
+    TradePair, TradeParser, TradeSide,
 };
-use awc::error::{PayloadError, SendRequestError};
-use chrono::NaiveDate;
-use log::error;
+use awc::error::PayloadError;
+use chrono::{Datelike, NaiveDate};
+use log::warn;
+use rust_decimal::Decimal;
 use serde::Deserialize;
 use std::fmt::Debug;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::io::AsyncRead;
 
-pub async fn aws_trade_reader(
-    client: &awc::Client,
+pub const DATA_URL: &str = "https://data.binance.vision";
+/// S3 bucket behind [`DATA_URL`], lists its archives.
+pub const BUCKET_URL: &str = "https://s3-ap-northeast-1.amazonaws.com/data.binance.vision";
+
+/// COIN-M quantities are converted from contracts with the contract size of
+/// `trade_pair`, pairs without a known size are rejected by
+/// [`coin_m_contract_size`].
+pub fn trade_parser(
+    market: Market,
+    dataset_type: DatasetType,
+    trade_pair: &TradePair,
+) -> TradeParser {
+    let contract_size = coin_m_contract_size(trade_pair);
+    match (market, dataset_type) {
+        (Market::Spot, DatasetType::Trades) => {
+            TradeParser::new(TRADE_COLUMNS, parse_spot_csv_trade)
+        }
+        (Market::Spot, DatasetType::AggTrades) => {
+            TradeParser::new(SPOT_AGG_TRADE_COLUMNS, parse_spot_csv_agg_trade)
+        }
+        (Market::UsdMFutures, DatasetType::Trades) => {
+            TradeParser::new(TRADE_COLUMNS, parse_csv_trade)
+        }
+        (Market::UsdMFutures, DatasetType::AggTrades) => {
+            TradeParser::new(AGG_TRADE_COLUMNS, parse_csv_agg_trade)
+        }
+        (Market::CoinMFutures, DatasetType::Trades) => TradeParser::new(
+            COIN_M_TRADE_COLUMNS,
+            match contract_size {
+                Some(100) => parse_coin_m_csv_trade::<100>,
+                _ => parse_coin_m_csv_trade::<10>,
+            },
+        ),
+        (Market::CoinMFutures, DatasetType::AggTrades) => TradeParser::new(
+            AGG_TRADE_COLUMNS,
+            match contract_size {
+                Some(100) => parse_coin_m_csv_agg_trade::<100>,
+                _ => parse_coin_m_csv_agg_trade::<10>,
+            },
+        ),
+    }
+}
+
+/// Spot headers spell the same columns in camel case, e.g. `quoteQty`.
+const TRADE_COLUMNS: &[&str] = &["id", "price", "qty", "quote_qty", "time", "is_buyer_maker"];
+const COIN_M_TRADE_COLUMNS: &[&str] = &["id", "price", "qty", "base_qty", "time", "is_buyer_maker"];
+const AGG_TRADE_COLUMNS: &[&str] = &[
+    "agg_trade_id",
+    "price",
+    "quantity",
+    "first_trade_id",
+    "last_trade_id",
+    "transact_time",
+    "is_buyer_maker",
+];
+const SPOT_AGG_TRADE_COLUMNS: &[&str] = &[
+    "agg_id",
+    "price",
+    "qty",
+    "first_id",
+    "last_id",
+    "time",
+    "is_buyer_maker",
+];
+const CANDLE_COLUMNS: &[&str] = &["open_time", "open", "high", "low", "close", "volume"];
+
+/// Covers `from..=to` with monthly archives for whole calendar months and
+/// daily archives for the partial months at the edges.
+pub fn archive_periods(from: NaiveDate, to: NaiveDate) -> Vec<ArchivePeriod> {
+    let mut periods = Vec::new();
+    let mut date = from;
+    while date <= to {
+        let whole_month = first_day_of_next_month(date)
+            .filter(|next_month| date.day() == 1 && next_month.pred_opt() <= Some(to));
+        let next = match whole_month {
+            Some(next_month) => {
+                periods.push(ArchivePeriod::Monthly {
+                    year: date.year(),
+                    month: date.month(),
+                });
+                Some(next_month)
+            }
+            None => {
+                periods.push(ArchivePeriod::Daily(date));
+                date.succ_opt()
+            }
+        };
+        match next {
+            Some(next) => date = next,
+            None => break,
+        }
+    }
+    periods
+}
+
+fn first_day_of_next_month(date: NaiveDate) -> Option<NaiveDate> {
+    match date.month() {
+        12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+        month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1),
+    }
+}
+
+/// Binance publishes every archive both per day and per calendar month.
+fn period_path(period: ArchivePeriod) -> &'static str {
+    match period {
+        ArchivePeriod::Daily(_) => "daily",
+        ArchivePeriod::Monthly { .. } => "monthly",
+    }
+}
+
+pub async fn aws_candle_reader(
+    client: &ArchiveClient,
+    market: Market,
+    interval: KlineInterval,
     trade_pair: &TradePair,
-    date: NaiveDate,
-) -> Result<ZipCsvTradeReader<impl AsyncRead + Unpin + Sized>, HttpZipReaderError> {
-    let url = trades_archive_url(trade_pair, date);
+    period: ArchivePeriod,
+) -> Result<CsvTradeReader<Candle>, HttpZipReaderError> {
+    let url = klines_archive_url(market, interval, trade_pair, period);
     let lines_reader = http_zip_lines_reader(client, &url).await?;
-    let trades_reader = ZipCsvTradeReader::new(lines_reader, parse_csv_trade);
-    Ok(trades_reader)
+    let candle_reader = CsvTradeReader::new(
+        lines_reader,
+        TradeParser::new(CANDLE_COLUMNS, parse_csv_candle),
+    );
+    Ok(candle_reader)
+}
+
+fn market_path(market: Market) -> &'static str {
+    match market {
+        Market::Spot => "spot",
+        Market::UsdMFutures => "futures/um",
+        Market::CoinMFutures => "futures/cm",
+    }
 }
 
-fn trades_archive_url(trade_pair: &TradePair, date: NaiveDate) -> String {
-    let formatted_pair = format!("{}{}", trade_pair.first, trade_pair.second);
+/// Archive symbol, e.g. `BTCUSDT`, `BTCUSD_PERP` or `BTCUSD_221230`.
+/// COIN-M pairs without a contract suffix default to the perpetual.
+fn archive_symbol(market: Market, trade_pair: &TradePair) -> String {
+    let pair = format!("{}{}", trade_pair.first, trade_pair.second);
+    match (market, trade_pair.contract) {
+        (Market::Spot, _) | (Market::UsdMFutures, None | Some(Contract::Perpetual)) => pair,
+        (Market::CoinMFutures, None | Some(Contract::Perpetual)) => format!("{}_PERP", pair),
+        (_, Some(contract)) => format!("{}_{}", pair, contract),
+    }
+}
+
+/// Quote assets, longer ones first so `USDT` wins over `USD`.
+const QUOTE_ASSETS: [&str; 18] = [
+    "FDUSD", "USDT", "BUSD", "USDC", "TUSD", "USDP", "BIDR", "IDRT", "USD", "DAI", "BTC", "ETH",
+    "BNB", "EUR", "GBP", "TRY", "BRL", "AUD",
+];
+
+/// Inverse of [`archive_symbol`]. COIN-M perpetuals come back without a
+/// contract, the same way they are requested.
+pub fn parse_archive_symbol(symbol: &str) -> Option<TradePair> {
+    let (pair, contract) = match symbol.split_once('_') {
+        Some((pair, "PERP")) => (pair, None),
+        Some((pair, contract)) => (pair, Some(contract.parse::<Contract>().ok()?)),
+        None => (symbol, None),
+    };
+    let (first, second) = QUOTE_ASSETS.iter().find_map(|quote| {
+        pair.strip_suffix(quote)
+            .filter(|first| !first.is_empty())
+            .map(|first| (first, *quote))
+    })?;
+    let trade_pair = TradePair::new(first, second);
+    Some(match contract {
+        Some(contract) => trade_pair.with_contract(contract),
+        None => trade_pair,
+    })
+}
+
+/// USD face value of a single COIN-M contract. COIN-M archives count quantity
+/// in contracts, not in base asset units.
+pub fn coin_m_contract_size(trade_pair: &TradePair) -> Option<u32> {
+    match &trade_pair.first[..] {
+        "BTC" => Some(100),
+        "ETH" | "BNB" | "LTC" | "BCH" | "XRP" | "EOS" | "TRX" | "ETC" | "LINK" | "ADA" | "DOT"
+        | "DOGE" | "SOL" | "FIL" | "UNI" | "XLM" | "AVAX" | "ATOM" | "MATIC" => Some(10),
+        _ => None,
+    }
+}
+
+pub fn trades_archive_url(
+    market: Market,
+    dataset_type: DatasetType,
+    trade_pair: &TradePair,
+    period: ArchivePeriod,
+) -> String {
+    let formatted_pair = archive_symbol(market, trade_pair);
     format!(
-        "https://data.binance.vision/data/futures/um/daily/trades/{}/{}-trades-{}.zip",
+        "{}/data/{}/{}/{}/{}/{}-{}-{}.zip",
+        DATA_URL,
+        market_path(market),
+        period_path(period),
+        dataset_type,
         formatted_pair,
         formatted_pair,
-        date.format("%Y-%m-%d")
+        dataset_type,
+        period
+    )
+}
+
+fn klines_archive_url(
+    market: Market,
+    interval: KlineInterval,
+    trade_pair: &TradePair,
+    period: ArchivePeriod,
+) -> String {
+    let formatted_pair = archive_symbol(market, trade_pair);
+    format!(
+        "{}/data/{}/{}/klines/{}/{}/{}-{}-{}.zip",
+        DATA_URL,
+        market_path(market),
+        period_path(period),
+        formatted_pair,
+        interval,
+        formatted_pair,
+        interval,
+        period
     )
 }
 
@@ -40,27 +243,117 @@ fn parse_side(raw: &str) -> Option<TradeSide> {
     }
 }
 
+fn parse_spot_side(raw: &str) -> Option<TradeSide> {
+    match raw {
+        "False" => Some(TradeSide::Buy),
+        "True" => Some(TradeSide::Sell),
+        _ => None,
+    }
+}
+
+/// Futures layout: `id,price,qty,quote_qty,time,is_buyer_maker`.
 fn parse_csv_trade(row: String) -> Result<Trade, ParseTradeError> {
+    parse_csv_trade_with_side(row, 2, parse_side, |_, columns| {
+        Ok((parse_quantity(columns, 0)?, parse_quantity(columns, 1)?))
+    })
+}
+
+/// COIN-M layout: `id,price,qty,base_qty,time,is_buyer_maker` with `qty` in
+/// contracts worth `CONTRACT_SIZE` USD.
+fn parse_coin_m_csv_trade<const CONTRACT_SIZE: u32>(row: String) -> Result<Trade, ParseTradeError> {
+    parse_csv_trade_with_side(row, 2, parse_side, |_, columns| {
+        let contracts = parse_quantity(columns, 0)?;
+        Ok((
+            parse_quantity(columns, 1)?,
+            contracts * Decimal::from(CONTRACT_SIZE),
+        ))
+    })
+}
+
+/// Spot layout: `id,price,qty,quoteQty,time,isBuyerMaker,isBestMatch`,
+/// with capitalized booleans.
+fn parse_spot_csv_trade(row: String) -> Result<Trade, ParseTradeError> {
+    parse_csv_trade_with_side(row, 2, parse_spot_side, |_, columns| {
+        Ok((parse_quantity(columns, 0)?, parse_quantity(columns, 1)?))
+    })
+}
+
+/// Futures layout: `agg_trade_id,price,quantity,first_trade_id,last_trade_id,transact_time,is_buyer_maker`.
+fn parse_csv_agg_trade(row: String) -> Result<Trade, ParseTradeError> {
+    parse_csv_trade_with_side(row, 3, parse_side, base_quantity)
+}
+
+/// COIN-M aggregated trades share the futures layout, with `quantity` in
+/// contracts worth `CONTRACT_SIZE` USD.
+fn parse_coin_m_csv_agg_trade<const CONTRACT_SIZE: u32>(
+    row: String,
+) -> Result<Trade, ParseTradeError> {
+    parse_csv_trade_with_side(row, 3, parse_side, |price, columns| {
+        let quote_quantity = parse_quantity(columns, 0)? * Decimal::from(CONTRACT_SIZE);
+        let quantity = quote_quantity
+            .checked_div(price)
+            .ok_or(ParseTradeError::ZeroPrice)?;
+        Ok((quantity, quote_quantity))
+    })
+}
+
+/// Spot layout: `aggId,price,qty,firstId,lastId,time,isBuyerMaker,isBestMatch`.
+fn parse_spot_csv_agg_trade(row: String) -> Result<Trade, ParseTradeError> {
+    parse_csv_trade_with_side(row, 3, parse_spot_side, base_quantity)
+}
+
+/// Base and quote quantity from the columns between price and time.
+type Quantities = fn(Decimal, &[&str]) -> Result<(Decimal, Decimal), ParseTradeError>;
+
+/// Quantity in the first column is in base asset.
+fn base_quantity(price: Decimal, columns: &[&str]) -> Result<(Decimal, Decimal), ParseTradeError> {
+    let quantity = parse_quantity(columns, 0)?;
+    Ok((quantity, quantity * price))
+}
+
+fn parse_quantity(columns: &[&str], index: usize) -> Result<Decimal, ParseTradeError> {
+    columns
+        .get(index)
+        .ok_or(ParseTradeError::MissingQuantity)?
+        .parse::<Decimal>()
+        .map_err(ParseTradeError::InvalidQuantity)
+}
+
+/// Spot archives have microsecond timestamps since 2025, the rest
+/// milliseconds, so the unit is detected per row.
+///
+/// Reads `id,price`, passes the next `columns_before_time` columns to
+/// `quantities` for the base and quote quantity and reads `time,is_buyer_maker`.
+fn parse_csv_trade_with_side(
+    row: String,
+    columns_before_time: usize,
+    parse_side: fn(&str) -> Option<TradeSide>,
+    quantities: Quantities,
+) -> Result<Trade, ParseTradeError> {
     let mut columns = row.split(',');
     let trade_id = columns
         .next()
         .map(|str| str.parse::<u64>())
-        .ok_or_else(|| ParseTradeError::MissingTradeId)?
+        .ok_or(ParseTradeError::MissingTradeId)?
         .map_err(|err| ParseTradeError::InvalidTradeId(row.clone(), err))?;
     let price = columns
         .next()
-        .map(|str| str.parse::<f64>())
-        .ok_or_else(|| ParseTradeError::MissingPrice)?
+        .map(|str| str.parse::<Decimal>())
+        .ok_or(ParseTradeError::MissingPrice)?
         .map_err(ParseTradeError::InvalidPrice)?;
-    let mut columns = columns.skip(2);
+    let quantity_columns = columns
+        .by_ref()
+        .take(columns_before_time)
+        .collect::<Vec<_>>();
+    let (quantity, quote_quantity) = quantities(price, &quantity_columns)?;
     let timestamp = columns
         .next()
-        .map(|str| str.parse::<i64>())
-        .ok_or_else(|| ParseTradeError::MissingTimestamp)?
+        .map(|str| str.parse::<i64>().map(Timestamp::detect))
+        .ok_or(ParseTradeError::MissingTimestamp)?
         .map_err(ParseTradeError::InvalidTimestamp)?;
     let side = columns
         .next()
-        .ok_or_else(|| ParseTradeError::MissingSide)
+        .ok_or(ParseTradeError::MissingSide)
         .map(|column| {
             parse_side(column.trim_end()).ok_or_else(|| ParseTradeError::InvalidSide(row.clone()))
         })
@@ -69,18 +362,53 @@ fn parse_csv_trade(row: String) -> Result<Trade, ParseTradeError> {
         id: trade_id,
         side,
         price,
+        quantity,
+        quote_quantity,
         timestamp,
     })
 }
 
+/// Layout: `open_time,open,high,low,close,volume,close_time,quote_volume,count,...`.
+fn parse_csv_candle(row: String) -> Result<Candle, ParseTradeError> {
+    let mut columns = row.split(',');
+    let open_time = columns
+        .next()
+        .map(|str| str.parse::<i64>().map(Timestamp::detect))
+        .ok_or(ParseTradeError::MissingTimestamp)?
+        .map_err(ParseTradeError::InvalidTimestamp)?;
+    let mut next_price = |name| {
+        columns
+            .next()
+            .map(|str: &str| str.trim_end().parse::<Decimal>())
+            .ok_or(ParseTradeError::MissingCandleField(name))?
+            .map_err(|err| ParseTradeError::InvalidCandleField(name, err))
+    };
+    Ok(Candle {
+        open_time,
+        open: next_price("open")?,
+        high: next_price("high")?,
+        low: next_price("low")?,
+        close: next_price("close")?,
+        volume: next_price("volume")?,
+    })
+}
+
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct ListBucketResult {
     #[serde(rename = "IsTruncated")]
     truncated: bool,
     #[serde(rename = "NextMarker")]
     next_marker: Option<String>,
-    #[serde(rename = "Contents")]
+    #[serde(rename = "Contents", default)]
     contents: Vec<BucketContents>,
+    #[serde(rename = "CommonPrefixes", default)]
+    common_prefixes: Vec<BucketPrefix>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct BucketPrefix {
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -97,6 +425,13 @@ impl BucketContents {
     pub fn is_zip(&self) -> bool {
         self.key.ends_with(".zip")
     }
+
+    /// Date of a daily archive, e.g. `BTCUSDT-trades-2022-09-13.zip`.
+    pub fn archive_date(&self) -> Option<NaiveDate> {
+        let stem = self.key.strip_suffix(".zip")?;
+        let date = stem.get(stem.len().checked_sub(10)?..)?;
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+    }
 }
 
 #[derive(Debug, Error)]
@@ -104,55 +439,141 @@ pub enum ListBucketError {
     #[error("Serialize request error: {0}")]
     SerializeRequest(serde_urlencoded::ser::Error),
     #[error("Send request error: {0}")]
-    SendRequest(SendRequestError),
+    SendRequest(String),
     #[error("Read body error: {0}")]
     ReadBody(PayloadError),
     #[error("Parse response: {0}")]
     Parse(serde_xml_rs::Error),
 }
 
-pub async fn list_whole_bucket(trades: &str) -> Result<Vec<BucketContents>, ListBucketError> {
-    let client = awc::ClientBuilder::new()
-        .timeout(Duration::from_secs(40))
-        .finish();
+impl From<ListBucketError> for ExchangeError {
+    fn from(err: ListBucketError) -> Self {
+        ExchangeError::Listing(Box::new(err))
+    }
+}
+
+pub async fn list_whole_bucket(
+    client: &ArchiveClient,
+    market: Market,
+    dataset_type: DatasetType,
+    symbol: &str,
+) -> Result<Vec<BucketContents>, ListBucketError> {
+    let prefix = format!("{}{}/", dataset_prefix(market, dataset_type), symbol);
     let mut contents = Vec::new();
+    list_all_parts(client, &prefix, |mut result| {
+        contents.append(&mut result.contents)
+    })
+    .await?;
+    Ok(contents)
+}
+
+/// Every symbol with daily trade archives on `market`.
+pub async fn list_symbols(
+    client: &ArchiveClient,
+    market: Market,
+) -> Result<Vec<TradePair>, ListBucketError> {
+    let prefix = dataset_prefix(market, DatasetType::Trades);
+    let mut symbols = Vec::new();
+    list_all_parts(client, &prefix, |result| {
+        symbols.extend(result.common_prefixes.iter().filter_map(|common| {
+            let symbol = common.prefix.strip_prefix(&prefix)?.trim_end_matches('/');
+            parse_archive_symbol(symbol)
+        }))
+    })
+    .await?;
+    Ok(symbols)
+}
+
+/// Daily archives of `trade_pair` with their sizes, oldest first.
+pub async fn list_archive_days(
+    client: &ArchiveClient,
+    market: Market,
+    dataset_type: DatasetType,
+    trade_pair: &TradePair,
+) -> Result<Vec<(NaiveDate, u64)>, ListBucketError> {
+    let symbol = archive_symbol(market, trade_pair);
+    let contents = list_whole_bucket(client, market, dataset_type, &symbol).await?;
+    let mut days = contents
+        .iter()
+        .filter(|content| content.is_zip())
+        .filter_map(|content| Some((content.archive_date()?, content.size)))
+        .collect::<Vec<_>>();
+    days.sort();
+    Ok(days)
+}
+
+fn dataset_prefix(market: Market, dataset_type: DatasetType) -> String {
+    format!("data/{}/daily/{}/", market_path(market), dataset_type)
+}
+
+async fn list_all_parts(
+    client: &ArchiveClient,
+    prefix: &str,
+    mut on_part: impl FnMut(ListBucketResult),
+) -> Result<(), ListBucketError> {
     let mut marker = None;
-    let contents = loop {
-        let mut result = list_bucket_part(&client, trades, marker.as_deref()).await?;
-        contents.append(&mut result.contents);
-        marker = result.next_marker;
-        if !result.truncated {
-            break contents;
+    loop {
+        let result = list_bucket_part(client, prefix, marker.as_deref()).await?;
+        let truncated = result.truncated;
+        marker = result.next_marker.clone();
+        on_part(result);
+        if !truncated {
+            break Ok(());
         }
-    };
-    Ok(contents)
+    }
 }
 
+/// Lists from the first upstream of [`BUCKET_URL`] that answers.
 async fn list_bucket_part(
+    client: &ArchiveClient,
+    prefix: &str,
+    marker: Option<&str>,
+) -> Result<ListBucketResult, ListBucketError> {
+    let mut failure = None;
+    for bucket_url in client.upstreams().urls(BUCKET_URL) {
+        let (url, prefix, marker) = (
+            bucket_url.clone(),
+            prefix.to_string(),
+            marker.map(str::to_string),
+        );
+        let listed = client.http().run(move |http| {
+            Box::pin(async move { list_bucket_url(&http, &url, &prefix, marker.as_deref()).await })
+        });
+        let listed = listed
+            .await
+            .map_err(|err| ListBucketError::SendRequest(err.to_string()));
+        match listed.and_then(|listed| listed) {
+            Ok(result) => return Ok(result),
+            Err(err) => {
+                warn!("Could not list {}: {}", bucket_url, err);
+                failure = Some(err);
+            }
+        }
+    }
+    Err(failure.expect("Every url has an upstream"))
+}
+
+async fn list_bucket_url(
     client: &awc::Client,
-    trades: &str,
+    bucket_url: &str,
+    prefix: &str,
     marker: Option<&str>,
 ) -> Result<ListBucketResult, ListBucketError> {
     let mut response = client
-        .get("https://s3-ap-northeast-1.amazonaws.com/data.binance.vision")
+        .get(bucket_url)
+        .timeout(Duration::from_secs(40))
         .query(&[
             ("delimiter", "/"),
-            (
-                "prefix",
-                &format!("data/futures/um/daily/trades/{}/", trades),
-            ),
+            ("prefix", prefix),
             ("marker", marker.unwrap_or_default()),
         ])
         .map_err(ListBucketError::SerializeRequest)?
         .send()
         .await
-        .map_err(ListBucketError::SendRequest)?;
-    let body = response
-        .body()
-        .await
-        .map_err(ListBucketError::ReadBody)?;
-    let result: ListBucketResult = serde_xml_rs::from_str(&String::from_utf8_lossy(&body))
-        .map_err(ListBucketError::Parse)?;
+        .map_err(|err| ListBucketError::SendRequest(err.to_string()))?;
+    let body = response.body().await.map_err(ListBucketError::ReadBody)?;
+    let result: ListBucketResult =
+        serde_xml_rs::from_str(&String::from_utf8_lossy(&body)).map_err(ListBucketError::Parse)?;
     Ok(result)
 }
 
@@ -208,15 +629,307 @@ mod tests {
                         size: 1518388,
                     },
                 ],
+                common_prefixes: vec![],
             }
         );
     }
 
+    #[test]
+    fn deserialize_list_symbols() {
+        let xml_content = r#"
+        <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+            <Name>data.binance.vision</Name>
+            <Prefix>data/futures/cm/daily/trades/</Prefix>
+            <Marker/>
+            <MaxKeys>1000</MaxKeys>
+            <Delimiter>/</Delimiter>
+            <IsTruncated>false</IsTruncated>
+            <CommonPrefixes>
+                <Prefix>data/futures/cm/daily/trades/BTCUSD_PERP/</Prefix>
+            </CommonPrefixes>
+            <CommonPrefixes>
+                <Prefix>data/futures/cm/daily/trades/BTCUSD_221230/</Prefix>
+            </CommonPrefixes>
+        </ListBucketResult>
+        "#;
+
+        let result: ListBucketResult = serde_xml_rs::de::from_str(xml_content).unwrap();
+        assert!(!result.truncated);
+        assert!(result.contents.is_empty());
+        assert_eq!(
+            result.common_prefixes,
+            vec![
+                BucketPrefix {
+                    prefix: "data/futures/cm/daily/trades/BTCUSD_PERP/".to_string()
+                },
+                BucketPrefix {
+                    prefix: "data/futures/cm/daily/trades/BTCUSD_221230/".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bucket_contents_archive_date() {
+        let contents = |key: &str| BucketContents {
+            key: key.to_string(),
+            etag: String::new(),
+            size: 0,
+        };
+        assert_eq!(
+            contents("data/futures/um/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip")
+                .archive_date(),
+            Some(NaiveDate::from_ymd(2022, 9, 13))
+        );
+        assert_eq!(
+            contents("data/futures/um/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip.CHECKSUM")
+                .archive_date(),
+            None
+        );
+        assert_eq!(contents(".zip").archive_date(), None);
+    }
+
+    #[test]
+    fn test_parse_archive_symbol() {
+        let parse = |symbol| parse_archive_symbol(symbol).map(|pair| pair.to_string());
+        assert_eq!(parse("BTCUSDT"), Some("BTC-USDT".to_string()));
+        assert_eq!(parse("ETHBTC"), Some("ETH-BTC".to_string()));
+        assert_eq!(parse("BTCUSD_PERP"), Some("BTC-USD".to_string()));
+        assert_eq!(parse("BTCUSD_221230"), Some("BTC-USD-221230".to_string()));
+        assert_eq!(parse("USDT"), None);
+        assert_eq!(parse("BTCXYZ"), None);
+        for pair in ["BTC-USDT", "ETH-USD-221230"] {
+            let trade_pair: TradePair = pair.parse().unwrap();
+            let symbol = archive_symbol(Market::CoinMFutures, &trade_pair);
+            assert_eq!(parse_archive_symbol(&symbol), Some(trade_pair));
+        }
+    }
+
     #[test]
     fn test_trade_archive_filename() {
         assert_eq!(
-            trades_archive_url(&TradePair::new("BTC", "USDT"), NaiveDate::from_ymd(2022, 9, 13)),
+            trades_archive_url(
+                Market::UsdMFutures,
+                DatasetType::Trades,
+                &TradePair::new("BTC", "USDT"),
+                ArchivePeriod::Daily(NaiveDate::from_ymd(2022, 9, 13))
+            ),
             "https://data.binance.vision/data/futures/um/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip"
         );
+        assert_eq!(
+            trades_archive_url(
+                Market::Spot,
+                DatasetType::Trades,
+                &TradePair::new("BTC", "USDT"),
+                ArchivePeriod::Daily(NaiveDate::from_ymd(2022, 9, 13))
+            ),
+            "https://data.binance.vision/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip"
+        );
+        assert_eq!(
+            trades_archive_url(
+                Market::UsdMFutures,
+                DatasetType::AggTrades,
+                &TradePair::new("BTC", "USDT"),
+                ArchivePeriod::Daily(NaiveDate::from_ymd(2022, 9, 13))
+            ),
+            "https://data.binance.vision/data/futures/um/daily/aggTrades/BTCUSDT/BTCUSDT-aggTrades-2022-09-13.zip"
+        );
+    }
+
+    #[test]
+    fn test_coin_m_archive_filename() {
+        assert_eq!(
+            trades_archive_url(
+                Market::CoinMFutures,
+                DatasetType::Trades,
+                &TradePair::new("BTC", "USD"),
+                ArchivePeriod::Daily(NaiveDate::from_ymd(2022, 9, 13))
+            ),
+            "https://data.binance.vision/data/futures/cm/daily/trades/BTCUSD_PERP/BTCUSD_PERP-trades-2022-09-13.zip"
+        );
+        assert_eq!(
+            trades_archive_url(
+                Market::CoinMFutures,
+                DatasetType::Trades,
+                &TradePair::new("ETH", "USD")
+                    .with_contract(Contract::Delivery(NaiveDate::from_ymd(2022, 12, 30))),
+                ArchivePeriod::Daily(NaiveDate::from_ymd(2022, 9, 13))
+            ),
+            "https://data.binance.vision/data/futures/cm/daily/trades/ETHUSD_221230/ETHUSD_221230-trades-2022-09-13.zip"
+        );
+    }
+
+    #[test]
+    fn test_archive_periods() {
+        let day = |month, day| ArchivePeriod::Daily(NaiveDate::from_ymd(2022, month, day));
+        assert_eq!(
+            archive_periods(
+                NaiveDate::from_ymd(2022, 7, 30),
+                NaiveDate::from_ymd(2022, 10, 2)
+            ),
+            vec![
+                day(7, 30),
+                day(7, 31),
+                ArchivePeriod::Monthly {
+                    year: 2022,
+                    month: 8
+                },
+                ArchivePeriod::Monthly {
+                    year: 2022,
+                    month: 9
+                },
+                day(10, 1),
+                day(10, 2),
+            ]
+        );
+        assert_eq!(
+            archive_periods(
+                NaiveDate::from_ymd(2022, 9, 13),
+                NaiveDate::from_ymd(2022, 9, 13)
+            ),
+            vec![day(9, 13)]
+        );
+        assert_eq!(
+            archive_periods(NaiveDate::MAX, NaiveDate::MAX),
+            vec![ArchivePeriod::Daily(NaiveDate::MAX)]
+        );
+        assert_eq!(
+            ArchivePeriod::Monthly {
+                year: 2024,
+                month: 2
+            }
+            .days()
+            .len(),
+            29
+        );
+        assert_eq!(
+            trades_archive_url(
+                Market::UsdMFutures,
+                DatasetType::Trades,
+                &TradePair::new("BTC", "USDT"),
+                ArchivePeriod::Monthly {
+                    year: 2022,
+                    month: 8
+                }
+            ),
+            "https://data.binance.vision/data/futures/um/monthly/trades/BTCUSDT/BTCUSDT-trades-2022-08.zip"
+        );
+    }
+
+    #[test]
+    fn test_klines_archive_filename() {
+        assert_eq!(
+            klines_archive_url(
+                Market::UsdMFutures,
+                KlineInterval::Minute1,
+                &TradePair::new("BTC", "USDT"),
+                "2022-09".parse().unwrap()
+            ),
+            "https://data.binance.vision/data/futures/um/monthly/klines/BTCUSDT/1m/BTCUSDT-1m-2022-09.zip"
+        );
+        assert_eq!(
+            klines_archive_url(
+                Market::Spot,
+                KlineInterval::Hour4,
+                &TradePair::new("ETH", "BTC"),
+                "2022-09-13".parse().unwrap()
+            ),
+            "https://data.binance.vision/data/spot/daily/klines/ETHBTC/4h/ETHBTC-4h-2022-09-13.zip"
+        );
+    }
+
+    #[test]
+    fn parse_candle() {
+        let candle = parse_csv_candle(
+            "1663027200000,22302.10,22320.00,22290.50,22301.20,512.430,1663027259999,11432531.45,3512,250.120,5580261.19,0\n"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            candle,
+            Candle {
+                open_time: Timestamp::from_millis(1663027200000),
+                open: Decimal::new(2230210, 2),
+                high: Decimal::new(2232000, 2),
+                low: Decimal::new(2229050, 2),
+                close: Decimal::new(2230120, 2),
+                volume: Decimal::new(512430, 3),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_spot_trade() {
+        let trade = parse_spot_csv_trade(
+            "1839462131,20180.01,0.00210,42.378021,1663027200012,True,True\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(trade.id, 1839462131);
+        assert_eq!(trade.price, Decimal::new(2018001, 2));
+        assert_eq!(trade.quantity, Decimal::new(21, 4));
+        assert_eq!(trade.quote_quantity, Decimal::new(42378021, 6));
+        assert_eq!(trade.timestamp, Timestamp::from_millis(1663027200012));
+        assert_eq!(trade.side, TradeSide::Sell);
+    }
+
+    #[test]
+    fn parse_microsecond_spot_trade() {
+        let trade = parse_spot_csv_trade(
+            "4337151230,93576.00,0.00012,11.22912,1735689600123456,False,True\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(trade.timestamp.as_micros(), 1735689600123456);
+        assert_eq!(trade.timestamp.as_millis(), 1735689600123);
+    }
+
+    #[test]
+    fn parse_agg_trade() {
+        let trade = parse_csv_agg_trade(
+            "1556789983,20025.58,0.134,1816909525,1816909527,1663191423013,false\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(trade.id, 1556789983);
+        assert_eq!(serde_json::to_string(&trade.price).unwrap(), "\"20025.58\"");
+        assert_eq!(trade.quantity, Decimal::new(134, 3));
+        assert_eq!(trade.quote_quantity, Decimal::new(268342772, 5));
+        assert_eq!(trade.timestamp, Timestamp::from_millis(1663191423013));
+        assert_eq!(trade.side, TradeSide::Buy);
+    }
+
+    #[test]
+    fn parse_coin_m_quantities() {
+        let trade = parse_coin_m_csv_trade::<100>(
+            "51234567,20000.0,3,0.015,1663027200012,true\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(trade.quantity, Decimal::new(15, 3));
+        assert_eq!(trade.quote_quantity, Decimal::from(300));
+        let trade = parse_coin_m_csv_agg_trade::<10>(
+            "1556789983,1250.0,5,1816909525,1816909527,1663191423013,false\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(trade.quantity, Decimal::new(4, 2));
+        assert_eq!(trade.quote_quantity, Decimal::from(50));
+        let parse = trade_parser(
+            Market::CoinMFutures,
+            DatasetType::Trades,
+            &TradePair::new("ETH", "USD"),
+        );
+        let trade =
+            (parse.parse)("51234567,1250.0,3,0.024,1663027200012,true\n".to_string()).unwrap();
+        assert_eq!(trade.quote_quantity, Decimal::from(30));
+    }
+
+    #[test]
+    fn coin_m_contract_sizes() {
+        assert_eq!(
+            coin_m_contract_size(&TradePair::new("BTC", "USD")),
+            Some(100)
+        );
+        assert_eq!(
+            coin_m_contract_size(&TradePair::new("ETH", "USD")),
+            Some(10)
+        );
+        assert_eq!(coin_m_contract_size(&TradePair::new("PEPE", "USD")), None);
     }
 }
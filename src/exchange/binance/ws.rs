@@ -1,18 +1,15 @@
-use actix_codec::Framed;
 use actix_web::web::Bytes;
 use awc::error::{WsClientError, WsProtocolError};
-use awc::ws::{Codec, Frame, Message};
-use awc::BoxedSocket;
-use futures::stream::SplitStream;
+use awc::ws::{Frame, Message};
 use futures::{SinkExt, StreamExt};
 use log::{debug, error};
+use rust_decimal::Decimal;
 use serde_json::Value;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedSender;
-use tokio::task::spawn_local;
-use tokio::time::{sleep, Instant};
+use tokio::time::{interval_at, Instant};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 const KEEP_ALIVE_DELAY: Duration = Duration::from_secs(1);
@@ -38,23 +35,30 @@ impl Event {
     }
 }
 
+/// Listens on a thread of its own, as awc connections are not `Send`. The
+/// thread stops once the returned stream is dropped.
 pub fn listen_updates() -> UnboundedReceiverStream<AggrMessage> {
     let (msg_sender, msg_receiver) = mpsc::unbounded_channel();
-    spawn_local(async move {
-        loop {
-            let result = try_listen_updates(msg_sender.clone()).await;
-            match result {
-                Err(ListenError::ListenerClosed) => {
-                    debug!("Listener closed");
-                    return;
-                }
-                Err(err) => {
-                    error!("Caught error while listening to updates: {err}.");
+    std::thread::Builder::new()
+        .name("binance-ws".to_string())
+        .spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                loop {
+                    let result = try_listen_updates(msg_sender.clone()).await;
+                    match result {
+                        Err(ListenError::ListenerClosed) => {
+                            debug!("Listener closed");
+                            return;
+                        }
+                        Err(err) => {
+                            error!("Caught error while listening to updates: {err}.");
+                        }
+                        Ok(_) => {}
+                    }
                 }
-                Ok(_) => {}
-            }
-        }
-    });
+            })
+        })
+        .expect("Could not spawn websocket thread");
     msg_receiver.into()
 }
 
@@ -68,14 +72,22 @@ async fn try_listen_updates(listener: UnboundedSender<AggrMessage>) -> Result<()
         .connect()
         .await?;
 
-    let (mut output_stream, input_stream) = framed.split();
-    let (event_sender, mut event_receiver) = mpsc::unbounded_channel();
-    spawn_local(handle_ws_packets(input_stream, event_sender.clone()));
-
+    let (mut output_stream, mut input_stream) = framed.split();
     let mut last_heartbeat = Instant::now();
-    spawn_local(keep_alive(event_sender));
+    let mut keep_alive = interval_at(Instant::now() + KEEP_ALIVE_DELAY, KEEP_ALIVE_DELAY);
 
-    while let Some(event) = event_receiver.recv().await {
+    loop {
+        let event = tokio::select! {
+            frame = input_stream.next() => match frame {
+                Some(Ok(frame)) => match Event::from_frame(frame) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                Some(Err(err)) => Event::ProtocolError(err),
+                None => break,
+            },
+            _ = keep_alive.tick() => Event::KeepAlive,
+        };
         match event {
             Event::TextReceived(bytes) => {
                 let message = serde_json::from_slice::<Value>(&bytes)
@@ -110,36 +122,6 @@ async fn try_listen_updates(listener: UnboundedSender<AggrMessage>) -> Result<()
     Err(ListenError::EndOfStream)
 }
 
-async fn handle_ws_packets(
-    stream: SplitStream<Framed<BoxedSocket, Codec>>,
-    tx: UnboundedSender<Event>,
-) {
-    let mut event_stream = stream.map(|result| result.map(Event::from_frame));
-    while let Some(event_result) = event_stream.next().await {
-        match event_result {
-            Ok(Some(event)) => {
-                if tx.send(event).is_err() {
-                    break;
-                }
-            }
-            Ok(None) => {}
-            Err(err) => {
-                let _ = tx.send(Event::ProtocolError(err));
-                break;
-            }
-        }
-    }
-}
-
-async fn keep_alive(tx: UnboundedSender<Event>) {
-    loop {
-        sleep(KEEP_ALIVE_DELAY).await;
-        if tx.send(Event::KeepAlive).is_err() {
-            break;
-        }
-    }
-}
-
 #[derive(Debug, Error)]
 pub enum ListenError {
     #[error("Could not connect to ws server: {0:?}")]
@@ -173,7 +155,7 @@ pub struct AggrMessage {
     pub symbol: String,
     pub timestamp: SystemTime,
     pub trade_id: u64,
-    pub price: f64,
+    pub price: Decimal,
 }
 
 impl AggrMessage {
@@ -218,13 +200,15 @@ mod tests {
         )
             .unwrap();
 
+        let message = AggrMessage::from_json(value).unwrap();
+        assert_eq!(message.price.to_string(), "20025.58000000");
         assert_eq!(
-            AggrMessage::from_json(value).unwrap(),
+            message,
             AggrMessage {
                 symbol: "BTCUSDT".to_string(),
                 timestamp: UNIX_EPOCH + Duration::from_millis(1663191423013),
                 trade_id: 1556789983,
-                price: 20025.58,
+                price: Decimal::new(2002558000000, 8),
             }
         )
     }
@@ -0,0 +1,80 @@
+pub mod data;
+
+use crate::exchange::registry::{
+    archive_trades, ArchiveExchange, DatasetRequest, Exchange, ExchangeError,
+};
+use crate::exchange::trade_reader::{
+    ArchiveClient, ArchiveFormat, ArchiveUrl, Contract, CreateStream, Market, TradeParser,
+};
+use futures::future::BoxFuture;
+
+pub struct Bybit;
+
+impl Exchange for Bybit {
+    fn name(&self) -> &'static str {
+        "bybit"
+    }
+
+    fn markets(&self) -> &'static [Market] {
+        &[Market::UsdMFutures, Market::CoinMFutures]
+    }
+
+    fn sample_trade_every(&self) -> usize {
+        10
+    }
+
+    fn trades<'a>(
+        &'a self,
+        client: &'a ArchiveClient,
+        request: &'a DatasetRequest,
+        sample_every_n_trade: usize,
+    ) -> BoxFuture<'a, Result<CreateStream, ExchangeError>> {
+        Box::pin(archive_trades(self, client, request, sample_every_n_trade))
+    }
+}
+
+impl ArchiveExchange for Bybit {
+    fn archive_format(&self) -> ArchiveFormat {
+        ArchiveFormat::Gzip
+    }
+
+    /// Both markets share one directory, the pair has to belong to the market
+    /// asked for.
+    fn archive_urls(&self, request: &DatasetRequest) -> Result<Vec<ArchiveUrl>, ExchangeError> {
+        let contract_market = data::contract_market(&request.trade_pair);
+        match request.market.unwrap_or(contract_market) {
+            Market::Spot => {
+                return Err(ExchangeError::UnsupportedMarket(
+                    Market::Spot,
+                    self.name().to_string(),
+                ))
+            }
+            market if market != contract_market => {
+                return Err(ExchangeError::MarketMismatch(
+                    request.trade_pair.clone(),
+                    market,
+                ))
+            }
+            _ => {}
+        }
+        if let Some(contract @ Contract::Delivery(_)) = request.trade_pair.contract {
+            return Err(ExchangeError::UnsupportedContract(
+                contract,
+                self.name().to_string(),
+            ));
+        }
+        Ok(data::trades_archive_urls(
+            data::PUBLIC_DATA_URL,
+            &request.trade_pair,
+            request.from,
+            request.to,
+        )
+        .into_iter()
+        .map(ArchiveUrl::from)
+        .collect())
+    }
+
+    fn parser(&self, _request: &DatasetRequest) -> TradeParser {
+        data::TRADE_PARSER
+    }
+}
@@ -0,0 +1,245 @@
+use crate::exchange::trade_reader::{
+    Market, ParseTradeError, Timestamp, TimestampUnit, Trade, TradePair, TradeParser, TradeSide,
+};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::num::ParseIntError;
+
+pub const PUBLIC_DATA_URL: &str = "https://public.bybit.com";
+
+/// Daily archives from `from` to `to` inclusive. Files are gzip compressed
+/// CSVs cut at UTC midnight.
+pub fn trades_archive_urls(
+    base_url: &str,
+    trade_pair: &TradePair,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<String> {
+    from.iter_days()
+        .take_while(|date| *date <= to)
+        .map(|date| trades_archive_url(base_url, trade_pair, date))
+        .collect()
+}
+
+/// Market of the contract `trade_pair` names, both have archives under
+/// `trading/`. Inverse contracts are quoted in USD, linear ones in USDT or USDC.
+pub fn contract_market(trade_pair: &TradePair) -> Market {
+    match &trade_pair.second[..] {
+        "USD" => Market::CoinMFutures,
+        _ => Market::UsdMFutures,
+    }
+}
+
+fn trades_archive_url(base_url: &str, trade_pair: &TradePair, date: NaiveDate) -> String {
+    let symbol = format!("{}{}", trade_pair.first, trade_pair.second);
+    format!(
+        "{}/trading/{}/{}{}.csv.gz",
+        base_url,
+        symbol,
+        symbol,
+        date.format("%Y-%m-%d")
+    )
+}
+
+fn parse_side(raw: &str) -> Option<TradeSide> {
+    match raw {
+        "Buy" => Some(TradeSide::Buy),
+        "Sell" => Some(TradeSide::Sell),
+        _ => None,
+    }
+}
+
+/// Converts fractional seconds, e.g. `1663027200.5574`, to microseconds.
+fn parse_timestamp(raw: &str) -> Result<Timestamp, ParseTradeError> {
+    let (seconds, fraction) = raw.split_once('.').unwrap_or((raw, ""));
+    let micros: String = fraction
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(6)
+        .collect();
+    let seconds = seconds
+        .parse::<i64>()
+        .map_err(ParseTradeError::InvalidTimestamp)?;
+    let micros = micros
+        .parse::<i64>()
+        .map_err(ParseTradeError::InvalidTimestamp)?;
+    seconds
+        .checked_mul(1_000_000)
+        .and_then(|seconds| seconds.checked_add(micros))
+        .map(|micros| Timestamp::new(micros, TimestampUnit::Micros))
+        .ok_or_else(|| ParseTradeError::TimestampOutOfRange(raw.to_string()))
+}
+
+/// Trade ids are UUIDs, the first 64 bits are used as a numeric id.
+fn parse_match_id(raw: &str) -> Result<u64, ParseIntError> {
+    let hex = raw.replace('-', "");
+    u64::from_str_radix(hex.get(..16).unwrap_or(&hex), 16)
+}
+
+pub const TRADE_PARSER: TradeParser = TradeParser::new(
+    &[
+        "timestamp",
+        "symbol",
+        "side",
+        "size",
+        "price",
+        "tickDirection",
+        "trdMatchID",
+        "grossValue",
+        "homeNotional",
+        "foreignNotional",
+    ],
+    parse_trade,
+);
+
+/// Layout: `timestamp,symbol,side,size,price,tickDirection,trdMatchID,grossValue,homeNotional,foreignNotional`.
+fn parse_trade(row: String) -> Result<Trade, ParseTradeError> {
+    let columns = row.split(',').collect::<Vec<_>>();
+    let timestamp = columns
+        .first()
+        .map(|str| parse_timestamp(str))
+        .ok_or(ParseTradeError::MissingTimestamp)??;
+    let side = columns
+        .get(2)
+        .ok_or(ParseTradeError::MissingSide)
+        .map(|column| parse_side(column).ok_or_else(|| ParseTradeError::InvalidSide(row.clone())))
+        .and_then(|flatten| flatten)?;
+    let price = columns
+        .get(4)
+        .map(|str| str.parse::<Decimal>())
+        .ok_or(ParseTradeError::MissingPrice)?
+        .map_err(ParseTradeError::InvalidPrice)?;
+    let trade_id = columns
+        .get(6)
+        .map(|str| parse_match_id(str))
+        .ok_or(ParseTradeError::MissingTradeId)?
+        .map_err(|err| ParseTradeError::InvalidTradeId(row.clone(), err))?;
+    let quantity = parse_quantity(&columns, 8)?;
+    let quote_quantity = parse_quantity(&columns, 9)?;
+    Ok(Trade {
+        id: trade_id,
+        side,
+        price,
+        quantity,
+        quote_quantity,
+        timestamp,
+    })
+}
+
+/// `homeNotional` and `foreignNotional` are in base and quote asset whatever
+/// the contract type, unlike `size`.
+fn parse_quantity(columns: &[&str], index: usize) -> Result<Decimal, ParseTradeError> {
+    columns
+        .get(index)
+        .ok_or(ParseTradeError::MissingQuantity)?
+        .trim_end()
+        .parse::<Decimal>()
+        .map_err(ParseTradeError::InvalidQuantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::http_pool::HttpPool;
+    use crate::exchange::test_server::serve;
+    use crate::exchange::trade_reader::{
+        http_csv_range_reader, ArchiveClient, ArchiveFormat, TradeReader,
+    };
+    use actix_web::{web, HttpResponse};
+    use async_compression::tokio::bufread::GzipEncoder;
+    use futures::StreamExt;
+    use tokio::io::AsyncReadExt;
+
+    const FIXTURE: &str = "\
+timestamp,symbol,side,size,price,tickDirection,trdMatchID,grossValue,homeNotional,foreignNotional
+1663027200.5574,BTCUSDT,Sell,0.001,20180.5,ZeroMinusTick,5b2d5a5a-93ab-5e3e-8b8e-1c0c2c5e5a30,2.01805e+09,0.001,20.1805
+1663027201.1,BTCUSDT,Buy,0.250,20181.0,PlusTick,00000000-0000-0001-8b8e-1c0c2c5e5a31,5.04525e+11,0.25,5045.25
+";
+
+    async fn gzip(text: &str) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        GzipEncoder::new(text.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        compressed
+    }
+
+    #[test]
+    fn test_trade_archive_filename() {
+        assert_eq!(
+            trades_archive_url(
+                PUBLIC_DATA_URL,
+                &TradePair::new("BTC", "USDT"),
+                NaiveDate::from_ymd(2022, 9, 13)
+            ),
+            "https://public.bybit.com/trading/BTCUSDT/BTCUSDT2022-09-13.csv.gz"
+        );
+    }
+
+    #[test]
+    fn contract_market_by_quote() {
+        assert_eq!(
+            contract_market(&TradePair::new("BTC", "USDT")),
+            Market::UsdMFutures
+        );
+        assert_eq!(
+            contract_market(&TradePair::new("BTC", "USD")),
+            Market::CoinMFutures
+        );
+    }
+
+    #[test]
+    fn parse_fractional_timestamp() {
+        let micros = |raw| {
+            parse_timestamp(raw)
+                .map(|timestamp| timestamp.as_micros())
+                .ok()
+        };
+        assert_eq!(micros("1663027200.5574"), Some(1663027200557400));
+        assert_eq!(micros("1663027200.1"), Some(1663027200100000));
+        assert_eq!(micros("1663027200"), Some(1663027200000000));
+        assert!(matches!(
+            parse_timestamp("9223372036854775807.5"),
+            Err(ParseTradeError::TimestampOutOfRange(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn read_gzip_archive() {
+        let fixture = gzip(FIXTURE).await;
+        let base_url = serve(move |config| {
+            let fixture = fixture.clone();
+            config.route(
+                "/trading/BTCUSDT/BTCUSDT2022-09-13.csv.gz",
+                web::get().to(move || {
+                    let fixture = fixture.clone();
+                    async move { HttpResponse::Ok().body(fixture) }
+                }),
+            );
+        });
+
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let date = NaiveDate::from_ymd(2022, 9, 13);
+        let urls = trades_archive_urls(&base_url, &TradePair::new("BTC", "USDT"), date, date);
+        let trades = http_csv_range_reader(&client, ArchiveFormat::Gzip.into(), urls, TRADE_PARSER)
+            .await
+            .unwrap()
+            .stream(1)
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].timestamp.as_millis(), 1663027200557);
+        assert_eq!(trades[0].side, TradeSide::Sell);
+        assert_eq!(trades[0].price, Decimal::new(201805, 1));
+        assert_eq!(trades[0].id, 0x5b2d5a5a93ab5e3e);
+        assert_eq!(trades[0].quantity, Decimal::new(1, 3));
+        assert_eq!(trades[0].quote_quantity, Decimal::new(201805, 4));
+        assert_eq!(trades[1].id, 1);
+        assert_eq!(trades[1].side, TradeSide::Buy);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::exchange::trade_reader::HttpZipReaderError;
+use actix_http::StatusCode;
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::io;
+use tokio::io::{AsyncRead, ReadBuf};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("SHA-256 of {url} is {actual}, expected {expected}")]
+pub struct ChecksumMismatch {
+    pub url: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Fetches the SHA-256 published next to `url` as `{url}.CHECKSUM`, in the
+/// `sha256sum` format. Archives without one can not be verified.
+pub async fn fetch_checksum(
+    client: &awc::Client,
+    url: &str,
+) -> Result<Option<String>, HttpZipReaderError> {
+    let checksum_url = format!("{}.CHECKSUM", url);
+    let mut response = client
+        .get(&checksum_url)
+        .send()
+        .await
+        .map_err(|err| HttpZipReaderError::SendRequest(err.to_string()))?;
+    let code = response.status();
+    if code == StatusCode::NOT_FOUND {
+        warn!("{} not found, archive will not be verified", checksum_url);
+        return Ok(None);
+    } else if !code.is_success() {
+        return Err(HttpZipReaderError::InvalidStatusCode(code));
+    }
+    let body = response
+        .body()
+        .await
+        .map_err(HttpZipReaderError::ReadChecksum)?;
+    parse_checksum(&String::from_utf8_lossy(&body)).map(Some)
+}
+
+/// Hash from a `sha256sum` line, e.g. `{hash}  BTCUSDT-trades-2022-09-13.zip`.
+pub fn parse_checksum(body: &str) -> Result<String, HttpZipReaderError> {
+    match body.split_whitespace().next() {
+        Some(hash) if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(hash.to_ascii_lowercase())
+        }
+        _ => Err(HttpZipReaderError::InvalidChecksumFile(body.to_string())),
+    }
+}
+
+/// Hashes bytes as they are read and fails the read hitting EOF when the hash
+/// differs from `expected`. The error carries a [`ChecksumMismatch`].
+pub struct ChecksumReader<R> {
+    inner: R,
+    url: String,
+    expected: String,
+    hasher: Option<Sha256>,
+}
+
+impl<R> ChecksumReader<R> {
+    pub fn new(inner: R, url: &str, expected: String) -> Self {
+        Self {
+            inner,
+            url: url.to_string(),
+            expected,
+            hasher: Some(Sha256::new()),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ChecksumReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                let read = &buf.filled()[filled_before..];
+                if !read.is_empty() {
+                    if let Some(hasher) = &mut this.hasher {
+                        hasher.update(read);
+                    }
+                } else if let Some(hasher) = this.hasher.take() {
+                    let actual = hex::encode(hasher.finalize());
+                    if actual != this.expected {
+                        let mismatch = ChecksumMismatch {
+                            url: this.url.clone(),
+                            expected: this.expected.clone(),
+                            actual,
+                        };
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            mismatch,
+                        )));
+                    }
+                }
+                Poll::Ready(Ok(()))
+            }
+            other => other,
+        }
+    }
+}
+
+/// Checksum mismatch carried by an error of [`ChecksumReader`].
+pub fn checksum_mismatch(err: &io::Error) -> Option<&ChecksumMismatch> {
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<ChecksumMismatch>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[actix_web::test]
+    async fn verify_while_reading() {
+        let mut reader = ChecksumReader::new(&b"hello"[..], "hello", HELLO_SHA256.to_string());
+        let mut read = String::new();
+        reader.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "hello");
+
+        let mut reader = ChecksumReader::new(&b"hell"[..], "hell", HELLO_SHA256.to_string());
+        let err = reader.read_to_string(&mut read).await.unwrap_err();
+        let mismatch = checksum_mismatch(&err).unwrap();
+        assert_eq!(mismatch.url, "hell");
+        assert_eq!(mismatch.expected, HELLO_SHA256);
+    }
+}
@@ -0,0 +1,206 @@
+use actix_http::encoding::Decoder;
+use actix_http::{header, Payload, StatusCode};
+use actix_web::web::Bytes;
+use awc::error::{PayloadError, SendRequestError};
+use awc::ClientResponse;
+use futures::{Stream, StreamExt};
+use log::warn;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io;
+use tokio::time::sleep;
+
+pub type HttpResponse = ClientResponse<Decoder<Payload>>;
+
+type PayloadStream = Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>>;
+
+/// How many times and how patiently failed downloads are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one fails.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every next one.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.pow(attempt)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Sends `request` until it gets through with a status other than 5xx. The
+/// last response is returned as is when retries run out.
+pub async fn send_retrying(
+    retry: RetryPolicy,
+    url: &str,
+    request: impl Fn() -> awc::ClientRequest,
+) -> Result<HttpResponse, SendRequestError> {
+    let mut attempt = 0;
+    loop {
+        let response = request().send().await;
+        let failure = match &response {
+            Ok(response) if response.status().is_server_error() => response.status().to_string(),
+            Ok(_) => return response,
+            Err(err) => err.to_string(),
+        };
+        if attempt >= retry.retries {
+            return response;
+        }
+        let delay = retry.delay(attempt);
+        warn!(
+            "Request to {} failed ({}), retrying in {:?}",
+            url, failure, delay
+        );
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Body of a successful `response` which continues with `Range` requests
+/// from the last received byte when the connection drops. `If-Range` makes
+/// sure the rest comes from the same version of the file. Connections closed
+/// before `Content-Length` bytes arrived count as dropped, the payload just
+/// ends in that case. Offsets count the bytes as sent, so `response` has to be
+/// requested with `no_decompress`. The download fails when the server does not
+/// continue exactly at the offset, as the bytes before it are already out.
+pub fn resumable_body(
+    client: awc::Client,
+    retry: RetryPolicy,
+    url: &str,
+    etag: Option<String>,
+    response: HttpResponse,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse().ok());
+    let download = Download {
+        client,
+        retry,
+        url: url.to_string(),
+        etag,
+        length,
+        body: Some(Box::pin(response)),
+        offset: 0,
+        failures: 0,
+    };
+    futures::stream::unfold(Some(download), |download| async move {
+        let mut download = download?;
+        loop {
+            let body = match &mut download.body {
+                Some(body) => body,
+                None => match download.resume().await {
+                    Ok(body) => download.body.insert(body),
+                    Err(err) => return Some((Err(err), None)),
+                },
+            };
+            match body.next().await {
+                Some(Ok(bytes)) => {
+                    download.offset += bytes.len() as u64;
+                    download.failures = 0;
+                    return Some((Ok(bytes), Some(download)));
+                }
+                Some(Err(err)) => {
+                    warn!(
+                        "Download of {} interrupted at byte {}: {}",
+                        download.url, download.offset, err
+                    );
+                    download.body = None;
+                }
+                None if download.offset < download.length.unwrap_or(0) => {
+                    warn!(
+                        "Download of {} closed at byte {} of {}",
+                        download.url,
+                        download.offset,
+                        download.length.unwrap_or(0)
+                    );
+                    download.body = None;
+                }
+                None => return None,
+            }
+        }
+    })
+    .fuse()
+}
+
+struct Download {
+    client: awc::Client,
+    retry: RetryPolicy,
+    url: String,
+    etag: Option<String>,
+    /// Size of the whole file.
+    length: Option<u64>,
+    body: Option<PayloadStream>,
+    offset: u64,
+    /// Failures since bytes were last received.
+    failures: u32,
+}
+
+impl Download {
+    async fn resume(&mut self) -> io::Result<PayloadStream> {
+        loop {
+            if self.failures >= self.retry.retries {
+                return Err(io::Error::other(format!(
+                    "Download of {} interrupted at byte {}, gave up after {} retries",
+                    self.url, self.offset, self.retry.retries
+                )));
+            }
+            sleep(self.retry.delay(self.failures)).await;
+            self.failures += 1;
+            let mut request = self
+                .client
+                .get(&self.url)
+                .no_decompress()
+                .insert_header((header::RANGE, format!("bytes={}-", self.offset)));
+            if let Some(etag) = &self.etag {
+                request = request.insert_header((header::IF_RANGE, etag.as_str()));
+            }
+            match request.send().await {
+                Ok(response) if response.status() == StatusCode::PARTIAL_CONTENT => {
+                    return match range_start(&response) {
+                        Some(start) if start == self.offset => Ok(Box::pin(response)),
+                        _ => Err(io::Error::other(format!(
+                            "Could not resume {} at byte {}: server sent another range",
+                            self.url, self.offset
+                        ))),
+                    };
+                }
+                Ok(response) if !response.status().is_server_error() => {
+                    return Err(io::Error::other(format!(
+                        "Could not resume {} at byte {}: server returned {}",
+                        self.url,
+                        self.offset,
+                        response.status()
+                    )))
+                }
+                Ok(response) => warn!("Resuming {} failed: {}", self.url, response.status()),
+                Err(err) => warn!("Resuming {} failed: {}", self.url, err),
+            }
+        }
+    }
+}
+
+/// First byte of a `Content-Range: bytes {start}-{end}/{length}` response.
+fn range_start(response: &HttpResponse) -> Option<u64> {
+    response
+        .headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .parse()
+        .ok()
+}
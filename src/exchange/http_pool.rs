@@ -0,0 +1,108 @@
+use crate::exchange::proxy::Proxy;
+use futures::future::LocalBoxFuture;
+use futures::{Stream, StreamExt};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+
+type Job = Box<dyn FnOnce(awc::Client) -> LocalBoxFuture<'static, ()> + Send>;
+
+/// Threads every upstream request runs on, taking requests in turns. awc is
+/// not `Send`, so its clients, connections and response bodies stay on them
+/// and only their results leave, which lets readers and parsers run on any
+/// thread. The threads stop once the last handle is dropped.
+#[derive(Clone)]
+pub struct HttpPool {
+    threads: Arc<[mpsc::UnboundedSender<Job>]>,
+    next: Arc<AtomicUsize>,
+}
+
+impl HttpPool {
+    /// Starts `threads` threads, at least one.
+    pub fn spawn(proxy: Option<Proxy>, threads: usize) -> Self {
+        let threads = (0..threads.max(1))
+            .map(|index| spawn_thread(proxy.clone(), index))
+            .collect();
+        Self {
+            threads,
+            next: Arc::default(),
+        }
+    }
+
+    /// Runs `request` with the client of the next thread and returns its
+    /// output. Fails only when the thread stopped.
+    pub async fn run<T, F>(&self, request: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(awc::Client) -> LocalBoxFuture<'static, T> + Send + 'static,
+    {
+        let (result_writer, result_reader) = oneshot::channel();
+        let job: Job = Box::new(move |client| {
+            Box::pin(async move {
+                let _ = result_writer.send(request(client).await);
+            })
+        });
+        let thread = self.next.fetch_add(1, Ordering::Relaxed) % self.threads.len();
+        self.threads[thread].send(job).map_err(|_| stopped())?;
+        result_reader.await.map_err(|_| stopped())
+    }
+}
+
+fn spawn_thread(proxy: Option<Proxy>, index: usize) -> mpsc::UnboundedSender<Job> {
+    let (jobs, mut receiver) = mpsc::unbounded_channel::<Job>();
+    std::thread::Builder::new()
+        .name(format!("http-{}", index))
+        .spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                let client = match &proxy {
+                    Some(proxy) => awc::Client::builder().connector(proxy.connector()).finish(),
+                    None => awc::Client::new(),
+                };
+                while let Some(job) = receiver.recv().await {
+                    actix_web::rt::spawn(job(client.clone()));
+                }
+            })
+        })
+        .expect("Could not spawn HTTP thread");
+    jobs
+}
+
+fn stopped() -> io::Error {
+    io::Error::other("HTTP thread stopped")
+}
+
+/// Forwards a response body out of a request running on the [`HttpPool`], it
+/// keeps being read there until the returned stream is dropped.
+pub fn forward<T: Send + 'static>(stream: impl Stream<Item = T> + 'static) -> ReceiverStream<T> {
+    let (writer, receiver) = mpsc::channel(16);
+    actix_web::rt::spawn(async move {
+        let mut stream = Box::pin(stream);
+        while let Some(item) = stream.next().await {
+            if writer.send(item).await.is_err() {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn run_on_threads_in_turns() {
+        let pool = HttpPool::spawn(None, 2);
+        let mut threads = Vec::new();
+        for _ in 0..4 {
+            let thread = pool
+                .run(|_| Box::pin(async { std::thread::current().name().map(str::to_string) }))
+                .await
+                .unwrap();
+            threads.push(thread.unwrap());
+        }
+        assert_eq!(threads, vec!["http-0", "http-1", "http-0", "http-1"]);
+    }
+}
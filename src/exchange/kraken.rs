@@ -0,0 +1,54 @@
+pub mod data;
+
+use crate::exchange::kraken::data::TradesPageError;
+use crate::exchange::registry::{DatasetRequest, Exchange, ExchangeError};
+use crate::exchange::trade_reader::{ArchiveClient, CreateStream, Market, TradeReader};
+use futures::future::BoxFuture;
+
+pub struct Kraken;
+
+impl Exchange for Kraken {
+    fn name(&self) -> &'static str {
+        "kraken"
+    }
+
+    fn markets(&self) -> &'static [Market] {
+        &[Market::Spot]
+    }
+
+    /// Trades come from the REST API, which is slow enough already.
+    fn sample_trade_every(&self) -> usize {
+        1
+    }
+
+    fn trades<'a>(
+        &'a self,
+        client: &'a ArchiveClient,
+        request: &'a DatasetRequest,
+        sample_every_n_trade: usize,
+    ) -> BoxFuture<'a, Result<CreateStream, ExchangeError>> {
+        Box::pin(async move {
+            if request.trade_pair.contract.is_some() {
+                return Err(ExchangeError::SpotContract);
+            }
+            if (request.to - request.from).num_days() >= data::MAX_RANGE_DAYS {
+                return Err(ExchangeError::UnsupportedRange(
+                    data::MAX_RANGE_DAYS,
+                    self.name().to_string(),
+                ));
+            }
+            let reader = data::rest_trade_reader(
+                client.http(),
+                &request.trade_pair,
+                request.from,
+                request.to,
+            )
+            .await
+            .map_err(|err| match err {
+                TradesPageError::UnknownPair => ExchangeError::NotFound,
+                other => ExchangeError::from(other),
+            })?;
+            Ok(reader.stream(sample_every_n_trade).await)
+        })
+    }
+}
@@ -0,0 +1,441 @@
+use crate::exchange::http_pool::HttpPool;
+use crate::exchange::registry::ExchangeError;
+use crate::exchange::trade_reader::{
+    CreateStreamFuture, ReadResult, Timestamp, TimestampUnit, Trade, TradePair, TradeReader,
+    TradeReaderError, TradeSide,
+};
+use actix_http::StatusCode;
+use awc::error::PayloadError;
+use chrono::NaiveDate;
+use futures::{future, stream, Stream, StreamExt};
+use log::warn;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::pin::Pin;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::sleep;
+
+const PUBLIC_API_URL: &str = "https://api.kraken.com";
+/// Public endpoints allow roughly one call per second.
+const PAGE_DELAY: Duration = Duration::from_secs(1);
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(2);
+const RATE_LIMIT_RETRIES: u32 = 5;
+/// Busy pairs take dozens of pages a day, at a page per second.
+pub const MAX_RANGE_DAYS: i64 = 7;
+
+/// Walks the public `Trades` endpoint with its `since` cursor. Kraken has no
+/// trade archives, so this is the only way to backfill history.
+pub struct RestTradeReader {
+    client: HttpPool,
+    base_url: String,
+    pair: String,
+    window: Range<Timestamp>,
+    first_page: TradesPage,
+    page_delay: Duration,
+    rate_limit_backoff: Duration,
+}
+
+/// Reads trades from `from` to `to` inclusive. The first page is fetched
+/// eagerly, so an unknown pair is reported up front.
+pub async fn rest_trade_reader(
+    client: &HttpPool,
+    trade_pair: &TradePair,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<RestTradeReader, TradesPageError> {
+    RestTradeReader::open(
+        client,
+        PUBLIC_API_URL,
+        trade_pair,
+        from,
+        to,
+        PAGE_DELAY,
+        RATE_LIMIT_BACKOFF,
+    )
+    .await
+}
+
+impl RestTradeReader {
+    async fn open(
+        client: &HttpPool,
+        base_url: &str,
+        trade_pair: &TradePair,
+        from: NaiveDate,
+        to: NaiveDate,
+        page_delay: Duration,
+        rate_limit_backoff: Duration,
+    ) -> Result<Self, TradesPageError> {
+        let window = Timestamp::days(from, to);
+        let pair = format!(
+            "{}{}",
+            asset_name(&trade_pair.first),
+            asset_name(&trade_pair.second)
+        );
+        let since = (window.start.as_micros() * 1_000).to_string();
+        let first_page = fetch_page(client, base_url, &pair, &since, rate_limit_backoff).await?;
+        Ok(Self {
+            client: client.clone(),
+            base_url: base_url.to_string(),
+            pair,
+            window,
+            first_page,
+            page_delay,
+            rate_limit_backoff,
+        })
+    }
+}
+
+enum PageState {
+    Page(TradesPage),
+    Failed(TradesPageError),
+    Done,
+}
+
+impl TradeReader for RestTradeReader {
+    fn stream(self, sample_every_n_trade: usize) -> CreateStreamFuture {
+        let window = self.window.clone();
+        let pages = stream::unfold(PageState::Page(self.first_page), move |state| {
+            let client = self.client.clone();
+            let base_url = self.base_url.clone();
+            let pair = self.pair.clone();
+            let window_end = self.window.end;
+            let page_delay = self.page_delay;
+            let rate_limit_backoff = self.rate_limit_backoff;
+            async move {
+                let page = match state {
+                    PageState::Page(page) => page,
+                    PageState::Failed(err) => {
+                        return Some((vec![Err(err.into())], PageState::Done));
+                    }
+                    PageState::Done => return None,
+                };
+                let reached_end = match page.trades.last() {
+                    Some(trade) => trade.timestamp >= window_end,
+                    None => true,
+                };
+                let next = if reached_end || page.last == page.since {
+                    PageState::Done
+                } else {
+                    sleep(page_delay).await;
+                    match fetch_page(&client, &base_url, &pair, &page.last, rate_limit_backoff)
+                        .await
+                    {
+                        Ok(next_page) => PageState::Page(next_page),
+                        Err(err) => PageState::Failed(err),
+                    }
+                };
+                let trades: Vec<ReadResult> = page.trades.into_iter().map(Ok).collect();
+                Some((trades, next))
+            }
+        });
+        let trades: Pin<Box<dyn Stream<Item = ReadResult> + Send>> = Box::pin(
+            pages
+                .flat_map(stream::iter)
+                .filter(move |result| {
+                    future::ready(match result {
+                        Ok(trade) => window.contains(&trade.timestamp),
+                        Err(_) => true,
+                    })
+                })
+                .enumerate()
+                .filter(move |(index, _)| future::ready(index.is_multiple_of(sample_every_n_trade)))
+                .map(|(_, result)| result),
+        );
+        Box::pin(future::ready(Ok(trades)))
+    }
+}
+
+/// Kraken still uses legacy names for a few assets.
+fn asset_name(asset: &str) -> &str {
+    match asset {
+        "BTC" => "XBT",
+        "DOGE" => "XDG",
+        other => other,
+    }
+}
+
+struct TradesPage {
+    since: String,
+    trades: Vec<Trade>,
+    last: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradesResponse {
+    error: Vec<String>,
+    result: Option<HashMap<String, Value>>,
+}
+
+async fn fetch_page(
+    client: &HttpPool,
+    base_url: &str,
+    pair: &str,
+    since: &str,
+    rate_limit_backoff: Duration,
+) -> Result<TradesPage, TradesPageError> {
+    let mut backoff = rate_limit_backoff;
+    let mut retries = 0;
+    loop {
+        let (url, asset_pair, cursor) = (base_url.to_string(), pair.to_string(), since.to_string());
+        let page = client.run(move |http| {
+            Box::pin(async move { try_fetch_page(&http, &url, &asset_pair, &cursor).await })
+        });
+        let page = page
+            .await
+            .map_err(|err| TradesPageError::SendRequest(err.to_string()));
+        match page.and_then(|page| page) {
+            Err(TradesPageError::RateLimited) if retries < RATE_LIMIT_RETRIES => {
+                warn!("Kraken rate limit exceeded, retrying {pair} page in {backoff:?}");
+                sleep(backoff).await;
+                backoff *= 2;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn try_fetch_page(
+    client: &awc::Client,
+    base_url: &str,
+    pair: &str,
+    since: &str,
+) -> Result<TradesPage, TradesPageError> {
+    let mut response = client
+        .get(format!("{}/0/public/Trades", base_url))
+        .query(&[("pair", pair), ("since", since)])
+        .map_err(TradesPageError::SerializeRequest)?
+        .send()
+        .await
+        .map_err(|err| TradesPageError::SendRequest(err.to_string()))?;
+    let code = response.status();
+    if code == StatusCode::TOO_MANY_REQUESTS {
+        return Err(TradesPageError::RateLimited);
+    } else if !code.is_success() {
+        return Err(TradesPageError::InvalidStatusCode(code));
+    }
+    let body = response
+        .body()
+        .limit(16 * 1024 * 1024)
+        .await
+        .map_err(TradesPageError::ReadBody)?;
+    let response: TradesResponse = serde_json::from_slice(&body).map_err(TradesPageError::Parse)?;
+    if let Some(error) = response.error.first() {
+        return Err(match &error[..] {
+            "EAPI:Rate limit exceeded" => TradesPageError::RateLimited,
+            "EQuery:Unknown asset pair" => TradesPageError::UnknownPair,
+            _ => TradesPageError::Api(response.error.join(", ")),
+        });
+    }
+    let result = response.result.ok_or(TradesPageError::MalformedPage)?;
+    let last = match result.get("last") {
+        Some(Value::String(last)) => last.clone(),
+        Some(Value::Number(last)) => last.to_string(),
+        _ => return Err(TradesPageError::MalformedPage),
+    };
+    let trades = result
+        .iter()
+        .find(|(key, _)| *key != "last")
+        .and_then(|(_, trades)| trades.as_array())
+        .ok_or(TradesPageError::MalformedPage)?
+        .iter()
+        .map(parse_trade)
+        .collect::<Option<Vec<_>>>()
+        .ok_or(TradesPageError::MalformedPage)?;
+    Ok(TradesPage {
+        since: since.to_string(),
+        trades,
+        last,
+    })
+}
+
+/// Layout: `[price, volume, time, side, order_type, misc, trade_id]` with
+/// `time` in fractional seconds. Trades without an id make the page malformed.
+fn parse_trade(value: &Value) -> Option<Trade> {
+    let columns = value.as_array()?;
+    let price: Decimal = columns.first()?.as_str()?.parse().ok()?;
+    let quantity: Decimal = columns.get(1)?.as_str()?.parse().ok()?;
+    let timestamp = Timestamp::new(
+        (columns.get(2)?.as_f64()? * 1_000_000.0).round() as i64,
+        TimestampUnit::Micros,
+    );
+    let side = match columns.get(3)?.as_str()? {
+        "b" => TradeSide::Buy,
+        "s" => TradeSide::Sell,
+        _ => return None,
+    };
+    let id = columns.get(6)?.as_u64()?;
+    Some(Trade {
+        id,
+        side,
+        price,
+        quantity,
+        quote_quantity: quantity * price,
+        timestamp,
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum TradesPageError {
+    #[error("Serialize request error: {0}")]
+    SerializeRequest(serde_urlencoded::ser::Error),
+    #[error("Send request error: {0}")]
+    SendRequest(String),
+    #[error("Server returned invalid status code: {0}")]
+    InvalidStatusCode(StatusCode),
+    #[error("Read body error: {0}")]
+    ReadBody(PayloadError),
+    #[error("Parse response: {0}")]
+    Parse(serde_json::Error),
+    #[error("Rate limit exceeded")]
+    RateLimited,
+    #[error("Unknown asset pair")]
+    UnknownPair,
+    #[error("API error: {0}")]
+    Api(String),
+    #[error("Malformed trades page")]
+    MalformedPage,
+}
+
+impl From<TradesPageError> for ExchangeError {
+    fn from(err: TradesPageError) -> Self {
+        ExchangeError::Page(Box::new(err))
+    }
+}
+
+impl From<TradesPageError> for TradeReaderError {
+    fn from(err: TradesPageError) -> Self {
+        TradeReaderError::Page(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::test_server::serve;
+    use actix_web::{web, HttpResponse};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const DAY_START_NANOS: &str = "1663027200000000000";
+    const SECOND_PAGE_NANOS: &str = "1663027201000000000";
+
+    #[derive(Deserialize)]
+    struct TradesQuery {
+        pair: String,
+        since: String,
+    }
+
+    fn canned_page(query: &TradesQuery, second_page_calls: &AtomicUsize) -> serde_json::Value {
+        assert_eq!(query.pair, "XBTUSD");
+        match &query.since[..] {
+            DAY_START_NANOS => json!({
+                "error": [],
+                "result": {
+                    "XXBTZUSD": [
+                        ["20180.10000", "0.00500000", 1663027200.1234, "b", "l", "", 1],
+                        ["20180.20000", "0.10000000", 1663027200.9, "s", "m", "", 2],
+                    ],
+                    "last": SECOND_PAGE_NANOS,
+                }
+            }),
+            SECOND_PAGE_NANOS if second_page_calls.fetch_add(1, Ordering::SeqCst) == 0 => {
+                json!({ "error": ["EAPI:Rate limit exceeded"] })
+            }
+            SECOND_PAGE_NANOS => json!({
+                "error": [],
+                "result": {
+                    "XXBTZUSD": [
+                        ["20181.00000", "1.00000000", 1663027201.5, "b", "m", "", 3],
+                        ["20190.00000", "1.00000000", 1663113600.0, "s", "m", "", 4],
+                    ],
+                    "last": "1663113600000000000",
+                }
+            }),
+            other => panic!("Unexpected since cursor {other}"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn walk_trade_pages() {
+        let second_page_calls = Arc::new(AtomicUsize::new(0));
+        let base_url = serve(move |config| {
+            let second_page_calls = second_page_calls.clone();
+            config.route(
+                "/0/public/Trades",
+                web::get().to(move |query: web::Query<TradesQuery>| {
+                    let page = canned_page(&query, &second_page_calls);
+                    async move { HttpResponse::Ok().json(page) }
+                }),
+            );
+        });
+
+        let client = HttpPool::spawn(None, 1);
+        let date = NaiveDate::from_ymd(2022, 9, 13);
+        let trades = RestTradeReader::open(
+            &client,
+            &base_url,
+            &TradePair::new("BTC", "USD"),
+            date,
+            date,
+            Duration::ZERO,
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap()
+        .stream(1)
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(
+            trades.iter().map(|trade| trade.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(trades[0].timestamp.as_millis(), 1663027200123);
+        assert_eq!(trades[1].side, TradeSide::Sell);
+        assert_eq!(trades[2].price, Decimal::from(20181));
+        assert_eq!(
+            trades[2].quote_quantity,
+            trades[2].quantity * Decimal::from(20181)
+        );
+    }
+
+    #[actix_web::test]
+    async fn reject_trades_without_id() {
+        let base_url = serve(|config| {
+            config.route(
+                "/0/public/Trades",
+                web::get().to(|| async {
+                    HttpResponse::Ok().json(json!({
+                        "error": [],
+                        "result": {
+                            "XXBTZUSD": [["20180.10000", "0.00500000", 1663027200.1234, "b", "l", ""]],
+                            "last": SECOND_PAGE_NANOS,
+                        }
+                    }))
+                }),
+            );
+        });
+
+        let date = NaiveDate::from_ymd(2022, 9, 13);
+        let reader = RestTradeReader::open(
+            &HttpPool::spawn(None, 1),
+            &base_url,
+            &TradePair::new("BTC", "USD"),
+            date,
+            date,
+            Duration::ZERO,
+            Duration::from_millis(1),
+        )
+        .await;
+        assert!(matches!(reader, Err(TradesPageError::MalformedPage)));
+    }
+}
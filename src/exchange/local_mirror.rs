@@ -0,0 +1,117 @@
+use crate::exchange::checksum::{parse_checksum, ChecksumReader};
+use crate::exchange::trade_reader::HttpZipReaderError;
+use awc::http::Uri;
+use log::{debug, warn};
+use std::path::{Component, Path, PathBuf};
+use tokio::io;
+use tokio::io::AsyncRead;
+
+/// Archives downloaded beforehand, laid out like `wget --mirror` stores them,
+/// e.g. `{dir}/data.binance.vision/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip`.
+/// Archives missing from the mirror are not downloaded.
+#[derive(Debug, Clone)]
+pub struct LocalMirror {
+    dir: PathBuf,
+}
+
+impl LocalMirror {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Mirrored copy of an `http(s)` archive url.
+    pub fn path(&self, url: &str) -> Option<PathBuf> {
+        let uri = url.parse::<Uri>().ok()?;
+        if !matches!(uri.scheme_str(), Some("http") | Some("https")) {
+            return None;
+        }
+        let relative = relative_path(uri.path())?;
+        Some(self.dir.join(uri.host()?).join(relative))
+    }
+}
+
+/// Path of a `file://` url.
+pub fn file_url_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let relative = relative_path(path)?;
+    Some(Path::new("/").join(relative))
+}
+
+/// Rejects `..` so pairs from requests can not point outside of the mirror.
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path.trim_start_matches('/'));
+    match path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        true => Some(path.to_path_buf()),
+        false => None,
+    }
+}
+
+/// Opens a local archive, verified against its `.CHECKSUM` sibling when one
+/// was mirrored along with it.
+pub async fn open_local_archive(
+    path: &Path,
+    url: &str,
+    verify_checksum: bool,
+) -> Result<Box<dyn AsyncRead + Unpin + Send>, HttpZipReaderError> {
+    debug!("Reading {} from {}", url, path.display());
+    let file = tokio::fs::File::open(path).await.map_err(open_error)?;
+    if !verify_checksum {
+        return Ok(Box::new(file));
+    }
+    let mut checksum_path = path.as_os_str().to_owned();
+    checksum_path.push(".CHECKSUM");
+    match tokio::fs::read_to_string(&checksum_path).await {
+        Ok(checksum) => Ok(Box::new(ChecksumReader::new(
+            file,
+            url,
+            parse_checksum(&checksum)?,
+        ))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            warn!(
+                "{} not mirrored, archive will not be verified",
+                Path::new(&checksum_path).display()
+            );
+            Ok(Box::new(file))
+        }
+        Err(err) => Err(HttpZipReaderError::ReadFile(err)),
+    }
+}
+
+fn open_error(err: io::Error) -> HttpZipReaderError {
+    match err.kind() {
+        io::ErrorKind::NotFound => HttpZipReaderError::NotFound,
+        _ => HttpZipReaderError::ReadFile(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_path() {
+        let mirror = LocalMirror::new("/mnt/archives");
+        assert_eq!(
+            mirror.path("https://data.binance.vision/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip"),
+            Some(PathBuf::from("/mnt/archives/data.binance.vision/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip"))
+        );
+        assert_eq!(
+            mirror.path("https://static.okx.com/cdn/okex/traderecords/trades/daily/20220913/BTC-USDT-trades-2022-09-13.zip"),
+            Some(PathBuf::from("/mnt/archives/static.okx.com/cdn/okex/traderecords/trades/daily/20220913/BTC-USDT-trades-2022-09-13.zip"))
+        );
+        assert_eq!(
+            mirror
+                .path("https://data.binance.vision/data/spot/daily/trades/../../../../etc/passwd"),
+            None
+        );
+        assert_eq!(mirror.path("file:///mnt/archives/a.zip"), None);
+        assert_eq!(
+            file_url_path("file:///mnt/archives/a.zip"),
+            Some(PathBuf::from("/mnt/archives/a.zip"))
+        );
+        assert_eq!(file_url_path("file:///mnt/../etc/passwd"), None);
+    }
+}
@@ -1 +1,94 @@
 pub mod data;
+
+use crate::exchange::olx::data::InstrumentType;
+use crate::exchange::registry::{
+    archive_trades, ArchiveExchange, DatasetRequest, Exchange, ExchangeError,
+};
+use crate::exchange::trade_reader::{
+    ArchiveClient, ArchiveUrl, Contract, CreateStream, Market, TradePair, TradeParser,
+};
+use futures::future::BoxFuture;
+
+pub struct Olx;
+
+impl Exchange for Olx {
+    fn name(&self) -> &'static str {
+        "olx"
+    }
+
+    fn markets(&self) -> &'static [Market] {
+        &[Market::Spot, Market::UsdMFutures, Market::CoinMFutures]
+    }
+
+    fn sample_trade_every(&self) -> usize {
+        10
+    }
+
+    fn trades<'a>(
+        &'a self,
+        client: &'a ArchiveClient,
+        request: &'a DatasetRequest,
+        sample_every_n_trade: usize,
+    ) -> BoxFuture<'a, Result<CreateStream, ExchangeError>> {
+        Box::pin(archive_trades(self, client, request, sample_every_n_trade))
+    }
+}
+
+impl ArchiveExchange for Olx {
+    /// The market follows from the instrument.
+    fn archive_urls(&self, request: &DatasetRequest) -> Result<Vec<ArchiveUrl>, ExchangeError> {
+        if request.market == Some(Market::Spot) && request.trade_pair.contract.is_some() {
+            return Err(ExchangeError::SpotContract);
+        }
+        let instrument = instrument(request);
+        let instrument_market = InstrumentType::of(&instrument).market(&instrument);
+        if let Some(market) = request.market.filter(|market| *market != instrument_market) {
+            return Err(ExchangeError::MarketMismatch(
+                request.trade_pair.clone(),
+                market,
+            ));
+        }
+        if let Some(contract) = instrument
+            .contract
+            .filter(|_| !data::contract_value_known(&instrument))
+        {
+            return Err(ExchangeError::UnsupportedContract(
+                contract,
+                instrument.to_string(),
+            ));
+        }
+        let end = request
+            .to
+            .succ_opt()
+            .ok_or(ExchangeError::DateOutOfRange(request.to))?;
+        let window = request.from.and_hms(0, 0, 0)..end.and_hms(0, 0, 0);
+        Ok(data::window_archive_urls(&instrument, &window)
+            .into_iter()
+            .map(ArchiveUrl::from)
+            .collect())
+    }
+
+    fn archive_entries(&self) -> Option<&'static str> {
+        Some("*.csv")
+    }
+
+    fn parser(&self, request: &DatasetRequest) -> TradeParser {
+        data::trade_parser(&instrument(request))
+    }
+
+    fn trims_window(&self) -> bool {
+        true
+    }
+}
+
+/// Instrument of the requested pair, a derivatives market without a contract
+/// suffix means the perpetual swap.
+fn instrument(request: &DatasetRequest) -> TradePair {
+    let trade_pair = &request.trade_pair;
+    match (request.market, trade_pair.contract) {
+        (Some(Market::UsdMFutures | Market::CoinMFutures), None) => {
+            trade_pair.clone().with_contract(Contract::Perpetual)
+        }
+        _ => trade_pair.clone(),
+    }
+}
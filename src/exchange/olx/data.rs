@@ -1,27 +1,74 @@
 use crate::exchange::trade_reader::{
-    http_zip_lines_reader, HttpZipReaderError, ParseTradeError, Trade, TradePair, TradeSide,
-    ZipCsvTradeReader,
+    Contract, Market, ParseTradeError, Timestamp, Trade, TradePair, TradeParser, TradeSide,
 };
-use chrono::NaiveDate;
-use tokio::io::AsyncRead;
-
-pub async fn archived_trade_reader(
-    client: &awc::Client,
-    trade_pair: &TradePair,
-    date: NaiveDate,
-) -> Result<ZipCsvTradeReader<impl AsyncRead + Unpin>, HttpZipReaderError> {
-    let url = trades_archive_url(trade_pair, date);
-    let lines_reader = http_zip_lines_reader(client, &url).await?;
-    let trades_reader = ZipCsvTradeReader::new(lines_reader, parse_trade);
-    Ok(trades_reader)
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use rust_decimal::Decimal;
+use std::ops::Range;
+
+/// Daily archives are cut at midnight Hong Kong time, not UTC.
+const ARCHIVE_DAY_OFFSET_HOURS: i64 = 8;
+
+/// Archives of every archive day overlapping the UTC `window`. Trades outside
+/// of the window have to be trimmed by the reader.
+pub fn window_archive_urls(trade_pair: &TradePair, window: &Range<NaiveDateTime>) -> Vec<String> {
+    archive_days(window)
+        .map(|date| trades_archive_url(trade_pair, date))
+        .collect()
+}
+
+fn archive_days(window: &Range<NaiveDateTime>) -> impl Iterator<Item = NaiveDate> {
+    let offset = Duration::hours(ARCHIVE_DAY_OFFSET_HOURS);
+    let last = (window.end - Duration::milliseconds(1) + offset).date();
+    (window.start + offset)
+        .date()
+        .iter_days()
+        .take_while(move |date| *date <= last)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrumentType {
+    Spot,
+    Swap,
+    Futures,
+}
+
+impl InstrumentType {
+    pub fn of(trade_pair: &TradePair) -> Self {
+        match trade_pair.contract {
+            None => InstrumentType::Spot,
+            Some(Contract::Perpetual) => InstrumentType::Swap,
+            Some(Contract::Delivery(_)) => InstrumentType::Futures,
+        }
+    }
+
+    /// Derivatives quoted in `USD` are inverse (coin margined), the rest are
+    /// linear (stablecoin margined).
+    pub fn market(&self, trade_pair: &TradePair) -> Market {
+        match self {
+            InstrumentType::Spot => Market::Spot,
+            _ if trade_pair.second == "USD" => Market::CoinMFutures,
+            _ => Market::UsdMFutures,
+        }
+    }
 }
 
+/// Instrument id, e.g. `BTC-USDT`, `BTC-USDT-SWAP` or `BTC-USD-221230`.
+fn instrument_id(trade_pair: &TradePair) -> String {
+    match trade_pair.contract {
+        None => format!("{}-{}", trade_pair.first, trade_pair.second),
+        Some(Contract::Perpetual) => format!("{}-{}-SWAP", trade_pair.first, trade_pair.second),
+        Some(contract) => format!("{}-{}-{}", trade_pair.first, trade_pair.second, contract),
+    }
+}
+
+pub const DATA_URL: &str = "https://static.okx.com";
+
 fn trades_archive_url(trade_pair: &TradePair, date: NaiveDate) -> String {
     format!(
-        "https://static.okx.com/cdn/okex/traderecords/trades/daily/{}/{}-{}-trades-{}.zip",
+        "{}/cdn/okex/traderecords/trades/daily/{}/{}-trades-{}.zip",
+        DATA_URL,
         date.format("%Y%m%d"),
-        trade_pair.first,
-        trade_pair.second,
+        instrument_id(trade_pair),
         date.format("%Y-%m-%d")
     )
 }
@@ -34,31 +81,188 @@ fn parse_side(raw: &str) -> Option<TradeSide> {
     }
 }
 
-fn parse_trade(row: String) -> Result<Trade, ParseTradeError> {
+/// Sizes of `instrument` are in base asset for spot and in contracts for
+/// derivatives. Inverse contracts are worth 100 USD for BTC and 10 USD for the
+/// rest. Contract values of linear instruments differ per instrument and are
+/// not published with the archives, ones without a known value are rejected
+/// by [`contract_value_known`].
+pub fn trade_parser(instrument: &TradePair) -> TradeParser {
+    let parse = match InstrumentType::of(instrument).market(instrument) {
+        Market::Spot => parse_trade,
+        Market::UsdMFutures => linear_parser(instrument).unwrap_or(parse_trade),
+        Market::CoinMFutures if instrument.first == "BTC" => parse_inverse_trade::<100>,
+        Market::CoinMFutures => parse_inverse_trade::<10>,
+    };
+    TradeParser::new(TRADE_COLUMNS, parse)
+}
+
+/// Whether sizes of `instrument` can be converted from contracts.
+pub fn contract_value_known(instrument: &TradePair) -> bool {
+    InstrumentType::of(instrument).market(instrument) != Market::UsdMFutures
+        || linear_parser(instrument).is_some()
+}
+
+/// Parser of a linear instrument by its contract value in base asset, the
+/// `ctVal` of the OKX instruments API, e.g. 0.01 BTC for `BTC-USDT-SWAP`.
+fn linear_parser(instrument: &TradePair) -> Option<fn(String) -> Result<Trade, ParseTradeError>> {
+    if instrument.second != "USDT" {
+        return None;
+    }
+    Some(match instrument.first.as_str() {
+        "BTC" => parse_linear_trade::<1, 2>,
+        "ETH" | "BCH" => parse_linear_trade::<1, 1>,
+        "LTC" | "SOL" => parse_linear_trade::<1, 0>,
+        "ETC" => parse_linear_trade::<10, 0>,
+        "XRP" => parse_linear_trade::<100, 0>,
+        "DOGE" => parse_linear_trade::<1000, 0>,
+        _ => return None,
+    })
+}
+
+/// Headers name columns in English and Chinese, e.g. `trade_id/交易id`.
+const TRADE_COLUMNS: &[&str] = &["trade_id", "side", "size", "price", "created_time"];
+
+/// `size` in contracts worth `CONTRACT_VALUE` USD.
+fn parse_inverse_trade<const CONTRACT_VALUE: u32>(row: String) -> Result<Trade, ParseTradeError> {
+    let trade = parse_trade(row)?;
+    let quote_quantity = trade.quantity * Decimal::from(CONTRACT_VALUE);
+    Ok(Trade {
+        quantity: quote_quantity
+            .checked_div(trade.price)
+            .ok_or(ParseTradeError::ZeroPrice)?,
+        quote_quantity,
+        ..trade
+    })
+}
+
+/// `size` in contracts worth `MANTISSA` * 10^-`SCALE` of the base asset.
+fn parse_linear_trade<const MANTISSA: i64, const SCALE: u32>(
+    row: String,
+) -> Result<Trade, ParseTradeError> {
+    let trade = parse_trade(row)?;
+    let quantity = trade.quantity * Decimal::new(MANTISSA, SCALE);
+    Ok(Trade {
+        quantity,
+        quote_quantity: quantity * trade.price,
+        ..trade
+    })
+}
+
+/// Layout shared by every instrument type: `trade_id,side,size,price,created_time`.
+/// SWAP and FUTURES sizes are in contracts.
+pub fn parse_trade(row: String) -> Result<Trade, ParseTradeError> {
     let columns = row.split(',').collect::<Vec<_>>();
-    let trade_id = columns.first()
+    let trade_id = columns
+        .first()
         .map(|str| str.parse::<u64>())
-        .ok_or_else(|| ParseTradeError::MissingTradeId)?
+        .ok_or(ParseTradeError::MissingTradeId)?
         .map_err(|err| ParseTradeError::InvalidTradeId(row.clone(), err))?;
     let side = columns
         .get(1)
-        .ok_or_else(|| ParseTradeError::MissingSide)
+        .ok_or(ParseTradeError::MissingSide)
         .map(|column| parse_side(column).ok_or_else(|| ParseTradeError::InvalidSide(row.clone())))
         .and_then(|flatten| flatten)?;
+    let quantity = columns
+        .get(2)
+        .map(|str| str.parse::<Decimal>())
+        .ok_or(ParseTradeError::MissingQuantity)?
+        .map_err(ParseTradeError::InvalidQuantity)?;
     let price = columns
         .get(3)
-        .map(|str| str.parse::<f64>())
-        .ok_or_else(|| ParseTradeError::MissingPrice)?
+        .map(|str| str.parse::<Decimal>())
+        .ok_or(ParseTradeError::MissingPrice)?
         .map_err(ParseTradeError::InvalidPrice)?;
     let timestamp = columns
         .get(4)
-        .map(|str| str.trim_end().parse::<i64>())
-        .ok_or_else(|| ParseTradeError::MissingTimestamp)?
+        .map(|str| str.trim_end().parse::<i64>().map(Timestamp::from_millis))
+        .ok_or(ParseTradeError::MissingTimestamp)?
         .map_err(ParseTradeError::InvalidTimestamp)?;
     Ok(Trade {
         id: trade_id,
         side,
         price,
+        quantity,
+        quote_quantity: quantity * price,
         timestamp,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trade_archive_filename() {
+        let date = NaiveDate::from_ymd(2022, 9, 13);
+        assert_eq!(
+            trades_archive_url(&"BTC-USDT".parse().unwrap(), date),
+            "https://static.okx.com/cdn/okex/traderecords/trades/daily/20220913/BTC-USDT-trades-2022-09-13.zip"
+        );
+        assert_eq!(
+            trades_archive_url(&"BTC-USDT-SWAP".parse().unwrap(), date),
+            "https://static.okx.com/cdn/okex/traderecords/trades/daily/20220913/BTC-USDT-SWAP-trades-2022-09-13.zip"
+        );
+        assert_eq!(
+            trades_archive_url(&"BTC-USD-221230".parse().unwrap(), date),
+            "https://static.okx.com/cdn/okex/traderecords/trades/daily/20220913/BTC-USD-221230-trades-2022-09-13.zip"
+        );
+    }
+
+    #[test]
+    fn utc_day_spans_two_archive_days() {
+        let day = NaiveDate::from_ymd(2022, 9, 13);
+        let window = day.and_hms(0, 0, 0)..day.succ().and_hms(0, 0, 0);
+        assert_eq!(
+            archive_days(&window).collect::<Vec<_>>(),
+            vec![day, day.succ()]
+        );
+        let window = day.and_hms(0, 0, 0)..day.and_hms(16, 0, 0);
+        assert_eq!(archive_days(&window).collect::<Vec<_>>(), vec![day]);
+    }
+
+    #[test]
+    fn instrument_market() {
+        let market = |pair: &str| {
+            let pair: TradePair = pair.parse().unwrap();
+            InstrumentType::of(&pair).market(&pair)
+        };
+        assert_eq!(market("BTC-USD"), Market::Spot);
+        assert_eq!(market("BTC-USDT-SWAP"), Market::UsdMFutures);
+        assert_eq!(market("BTC-USD-SWAP"), Market::CoinMFutures);
+        assert_eq!(market("BTC-USD-221230"), Market::CoinMFutures);
+    }
+
+    #[test]
+    fn parse_quantities() {
+        let row = || "310034417,buy,2,20000.0,1663027200012".to_string();
+        let parse = |pair: &str| (trade_parser(&pair.parse().unwrap()).parse)(row()).unwrap();
+        let trade = parse("BTC-USDT");
+        assert_eq!(
+            (trade.quantity, trade.quote_quantity),
+            (Decimal::from(2), Decimal::from(40000))
+        );
+        let trade = parse("BTC-USD-SWAP");
+        assert_eq!(
+            (trade.quantity, trade.quote_quantity),
+            (Decimal::new(1, 2), Decimal::from(200))
+        );
+        let trade = parse("ETH-USD-221230");
+        assert_eq!(
+            (trade.quantity, trade.quote_quantity),
+            (Decimal::new(1, 3), Decimal::from(20))
+        );
+        let trade = parse("BTC-USDT-SWAP");
+        assert_eq!(
+            (trade.quantity, trade.quote_quantity),
+            (Decimal::new(2, 2), Decimal::from(400))
+        );
+        let trade = parse("DOGE-USDT-221230");
+        assert_eq!(
+            (trade.quantity, trade.quote_quantity),
+            (Decimal::from(2000), Decimal::from(40_000_000))
+        );
+        assert!(contract_value_known(&"PEPE-USDT".parse().unwrap()));
+        assert!(!contract_value_known(&"PEPE-USDT-SWAP".parse().unwrap()));
+        assert!(!contract_value_known(&"BTC-USDC-SWAP".parse().unwrap()));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::exchange::binance::Binance;
+use crate::exchange::bybit::Bybit;
+use crate::exchange::kraken::Kraken;
+use crate::exchange::olx::Olx;
+use crate::exchange::trade_reader::{
+    http_csv_range_reader, ArchiveClient, ArchiveFormat, ArchiveOptions, ArchivePeriod, ArchiveUrl,
+    Candle, Contract, CreateStream, DatasetType, HttpZipReaderError, KlineInterval, Market,
+    Timestamp, TradePair, TradeParser, TradeReader,
+};
+use chrono::NaiveDate;
+use futures::future;
+use futures::future::BoxFuture;
+use std::error::Error;
+use std::ops::Range;
+use thiserror::Error;
+
+/// Trades requested for UTC days `from..=to`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetRequest {
+    pub trade_pair: TradePair,
+    pub market: Option<Market>,
+    pub dataset_type: DatasetType,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl DatasetRequest {
+    /// Requested days as a timestamp range.
+    pub fn window(&self) -> Range<Timestamp> {
+        Timestamp::days(self.from, self.to)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct ArchiveDay {
+    pub date: NaiveDate,
+    /// Compressed size in bytes.
+    pub size: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum ExchangeError {
+    #[error("Spot pairs can not have a contract suffix")]
+    SpotContract,
+    #[error("Market {0} is not supported by {1}")]
+    UnsupportedMarket(Market, String),
+    #[error("Dataset type {0} is not supported by {1}")]
+    UnsupportedDataset(DatasetType, String),
+    #[error("Contract {0} is not supported by {1}")]
+    UnsupportedContract(Contract, String),
+    #[error("{0} is not traded on the {1} market")]
+    MarketMismatch(TradePair, Market),
+    #[error("Dataset for given parameters not found")]
+    NotFound,
+    #[error("Zip reader error: {0}")]
+    HttpZipReader(HttpZipReaderError),
+    /// Failure of a page of an API without archives.
+    #[error("Trades page error: {0}")]
+    Page(Box<dyn Error + Send + Sync>),
+    #[error("Listing archives is not supported by {0}")]
+    UnsupportedListing(String),
+    /// Failure listing what the exchange published.
+    #[error("Listing error: {0}")]
+    Listing(Box<dyn Error + Send + Sync>),
+    #[error("Candles are not supported by {0}")]
+    UnsupportedCandles(String),
+    #[error("Ranges of more than {0} days are not supported by {1}")]
+    UnsupportedRange(i64, String),
+    #[error("Date {0} is out of range")]
+    DateOutOfRange(NaiveDate),
+}
+
+impl From<HttpZipReaderError> for ExchangeError {
+    fn from(err: HttpZipReaderError) -> Self {
+        match err {
+            HttpZipReaderError::NotFound => ExchangeError::NotFound,
+            other => ExchangeError::HttpZipReader(other),
+        }
+    }
+}
+
+pub trait Exchange: Send + Sync {
+    /// Name used in routes, e.g. `binance`.
+    fn name(&self) -> &'static str;
+
+    /// Supported markets, the first one is used when the request has none.
+    fn markets(&self) -> &'static [Market];
+
+    fn dataset_types(&self) -> &'static [DatasetType] {
+        &[DatasetType::Trades]
+    }
+
+    /// Every n-th trade of a single day is sent to the browser.
+    fn sample_trade_every(&self) -> usize;
+
+    fn supports_candles(&self) -> bool {
+        false
+    }
+
+    /// Rejects markets and dataset types the exchange does not offer.
+    fn validate(&self, request: &DatasetRequest) -> Result<(), ExchangeError> {
+        if let Some(market) = request
+            .market
+            .filter(|market| !self.markets().contains(market))
+        {
+            return Err(ExchangeError::UnsupportedMarket(
+                market,
+                self.name().to_string(),
+            ));
+        }
+        if !self.dataset_types().contains(&request.dataset_type) {
+            return Err(ExchangeError::UnsupportedDataset(
+                request.dataset_type,
+                self.name().to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn trades<'a>(
+        &'a self,
+        client: &'a ArchiveClient,
+        request: &'a DatasetRequest,
+        sample_every_n_trade: usize,
+    ) -> BoxFuture<'a, Result<CreateStream, ExchangeError>>;
+
+    /// Candles of a day or a month, offered when [`Exchange::supports_candles`].
+    fn candles<'a>(
+        &'a self,
+        _client: &'a ArchiveClient,
+        _market: Market,
+        _interval: KlineInterval,
+        _trade_pair: &'a TradePair,
+        _period: ArchivePeriod,
+    ) -> BoxFuture<'a, Result<CreateStream<Candle>, ExchangeError>> {
+        Box::pin(future::ready(Err(ExchangeError::UnsupportedCandles(
+            self.name().to_string(),
+        ))))
+    }
+
+    /// Every pair with trades on `market`.
+    fn symbols(
+        &self,
+        _client: &ArchiveClient,
+        _market: Market,
+    ) -> BoxFuture<'static, Result<Vec<TradePair>, ExchangeError>> {
+        Box::pin(future::ready(Err(ExchangeError::UnsupportedListing(
+            self.name().to_string(),
+        ))))
+    }
+
+    /// Days with a published archive of `trade_pair`, oldest first.
+    fn availability(
+        &self,
+        _client: &ArchiveClient,
+        _market: Market,
+        _dataset_type: DatasetType,
+        _trade_pair: TradePair,
+    ) -> BoxFuture<'static, Result<Vec<ArchiveDay>, ExchangeError>> {
+        Box::pin(future::ready(Err(ExchangeError::UnsupportedListing(
+            self.name().to_string(),
+        ))))
+    }
+}
+
+/// Exchange publishing trades as CSV archives.
+pub trait ArchiveExchange: Exchange {
+    fn archive_format(&self) -> ArchiveFormat {
+        ArchiveFormat::Zip
+    }
+
+    /// Name pattern of the zip entries holding trades, every entry is read
+    /// when `None`.
+    fn archive_entries(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether archives have `.CHECKSUM` siblings to verify them with.
+    fn publishes_checksums(&self) -> bool {
+        false
+    }
+
+    /// Archives covering the request in chronological order.
+    fn archive_urls(&self, request: &DatasetRequest) -> Result<Vec<ArchiveUrl>, ExchangeError>;
+
+    fn parser(&self, request: &DatasetRequest) -> TradeParser;
+
+    /// Archives cut at other hours than UTC midnight need their rows trimmed.
+    fn trims_window(&self) -> bool {
+        false
+    }
+}
+
+/// Default [`Exchange::trades`] of an [`ArchiveExchange`].
+pub async fn archive_trades(
+    exchange: &impl ArchiveExchange,
+    client: &ArchiveClient,
+    request: &DatasetRequest,
+    sample_every_n_trade: usize,
+) -> Result<CreateStream, ExchangeError> {
+    let urls = exchange.archive_urls(request)?;
+    let options = ArchiveOptions {
+        format: exchange.archive_format(),
+        verify_checksum: exchange.publishes_checksums(),
+        entries: exchange.archive_entries(),
+    };
+    let reader = http_csv_range_reader(client, options, urls, exchange.parser(request)).await?;
+    let reader = match exchange.trims_window() {
+        true => reader.within(request.window()),
+        false => reader,
+    };
+    Ok(reader.stream(sample_every_n_trade).await)
+}
+
+pub struct ExchangeRegistry {
+    exchanges: Vec<Box<dyn Exchange>>,
+}
+
+impl ExchangeRegistry {
+    pub fn empty() -> Self {
+        Self {
+            exchanges: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, exchange: impl Exchange + 'static) -> Self {
+        self.exchanges.push(Box::new(exchange));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Exchange> {
+        self.exchanges
+            .iter()
+            .find(|exchange| exchange.name() == name)
+            .map(|exchange| exchange.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Exchange> {
+        self.exchanges.iter().map(|exchange| exchange.as_ref())
+    }
+}
+
+impl Default for ExchangeRegistry {
+    fn default() -> Self {
+        Self::empty()
+            .with(Binance)
+            .with(Olx)
+            .with(Bybit)
+            .with(Kraken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(market: Option<Market>, dataset_type: DatasetType) -> DatasetRequest {
+        let date = NaiveDate::from_ymd(2022, 9, 13);
+        DatasetRequest {
+            trade_pair: TradePair::new("BTC", "USDT"),
+            market,
+            dataset_type,
+            from: date,
+            to: date,
+        }
+    }
+
+    #[test]
+    fn lookup_by_name() {
+        let registry = ExchangeRegistry::default();
+        assert_eq!(
+            registry.iter().map(|e| e.name()).collect::<Vec<_>>(),
+            vec!["binance", "olx", "bybit", "kraken"]
+        );
+        assert_eq!(registry.get("kraken").map(|e| e.name()), Some("kraken"));
+        assert!(registry.get("binanse").is_none());
+    }
+
+    #[test]
+    fn validate_request() {
+        let registry = ExchangeRegistry::default();
+        let binance = registry.get("binance").unwrap();
+        let kraken = registry.get("kraken").unwrap();
+        assert!(binance
+            .validate(&request(Some(Market::Spot), DatasetType::AggTrades))
+            .is_ok());
+        assert!(matches!(
+            kraken.validate(&request(Some(Market::UsdMFutures), DatasetType::Trades)),
+            Err(ExchangeError::UnsupportedMarket(Market::UsdMFutures, _))
+        ));
+        assert!(matches!(
+            kraken.validate(&request(None, DatasetType::AggTrades)),
+            Err(ExchangeError::UnsupportedDataset(DatasetType::AggTrades, _))
+        ));
+    }
+}
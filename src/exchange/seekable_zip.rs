@@ -0,0 +1,410 @@
+use async_compression::tokio::bufread::DeflateDecoder;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::{File, OpenOptions};
+use tokio::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const LOCAL_HEADER_LEN: usize = 30;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+/// Sizes and offsets saying the real value is in the ZIP64 extra field.
+const ZIP64_MARKER: u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    compression: u16,
+    compressed_size: u64,
+    header_offset: u64,
+}
+
+impl ZipEntry {
+    pub fn dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+/// Zip spooled to a temp file, read through its central directory. Entries
+/// with ZIP64 sizes or data descriptors are fine, the file is removed in the
+/// background once the reader is dropped.
+pub struct SeekableZip {
+    file: File,
+    path: PathBuf,
+    entries: Vec<ZipEntry>,
+}
+
+impl SeekableZip {
+    /// Copies `source`, the rest of a zip whose first `offset` bytes were
+    /// already read, to a temp file and reads its central directory. Entries
+    /// starting before `offset` can not be read.
+    pub async fn spool(offset: u64, source: &mut (impl AsyncRead + Unpin)) -> io::Result<Self> {
+        static SPOOLED: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "spooled-{}-{}.zip",
+            std::process::id(),
+            SPOOLED.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+        let mut zip = Self {
+            file,
+            path,
+            entries: Vec::new(),
+        };
+        // the skipped bytes are left a hole of the file
+        zip.file.seek(SeekFrom::Start(offset)).await?;
+        io::copy(source, &mut zip.file).await?;
+        zip.file.flush().await?;
+        zip.entries = zip.read_central_directory().await?;
+        zip.entries.sort_by_key(|entry| entry.header_offset);
+        Ok(zip)
+    }
+
+    /// Entries in the order of their local headers.
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Decompressed content of the `index`-th entry.
+    pub async fn entry_reader(
+        &mut self,
+        index: usize,
+    ) -> io::Result<Box<dyn AsyncRead + Unpin + Send + '_>> {
+        let entry = self.entries[index].clone();
+        self.file.seek(SeekFrom::Start(entry.header_offset)).await?;
+        let mut header = [0u8; LOCAL_HEADER_LEN];
+        self.file.read_exact(&mut header).await?;
+        if u32_at(&header, 0) != LOCAL_HEADER {
+            return Err(invalid(format!(
+                "No local header of {} at {}",
+                entry.name, entry.header_offset
+            )));
+        }
+        let variable_len = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+        self.file.seek(SeekFrom::Current(variable_len)).await?;
+        let data = (&mut self.file).take(entry.compressed_size);
+        match entry.compression {
+            0 => Ok(Box::new(data)),
+            8 => Ok(Box::new(DeflateDecoder::new(BufReader::new(data)))),
+            method => Err(invalid(format!(
+                "Unsupported compression method {} of {}",
+                method, entry.name
+            ))),
+        }
+    }
+
+    async fn read_central_directory(&mut self) -> io::Result<Vec<ZipEntry>> {
+        let (count, size, offset) = self.locate_central_directory().await?;
+        let mut directory = vec![0u8; size as usize];
+        self.file.seek(SeekFrom::Start(offset)).await?;
+        self.file.read_exact(&mut directory).await?;
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut rest = directory.as_slice();
+        for _ in 0..count {
+            if rest.len() < 46 || u32_at(rest, 0) != CENTRAL_HEADER {
+                return Err(invalid("Truncated central directory"));
+            }
+            let name_len = u16_at(rest, 28) as usize;
+            let extra_len = u16_at(rest, 30) as usize;
+            let comment_len = u16_at(rest, 32) as usize;
+            let len = 46 + name_len + extra_len + comment_len;
+            if rest.len() < len {
+                return Err(invalid("Truncated central directory"));
+            }
+            let extra = &rest[46 + name_len..46 + name_len + extra_len];
+            let mut zip64 = zip64_fields(extra);
+            let uncompressed_size = u32_at(rest, 24);
+            // ZIP64 fields only hold values marked in the header, in this order
+            if uncompressed_size == ZIP64_MARKER {
+                zip64.next();
+            }
+            let compressed_size = match u32_at(rest, 20) {
+                ZIP64_MARKER => zip64.next().ok_or_else(|| invalid("Missing ZIP64 size"))?,
+                size => size as u64,
+            };
+            let header_offset = match u32_at(rest, 42) {
+                ZIP64_MARKER => zip64
+                    .next()
+                    .ok_or_else(|| invalid("Missing ZIP64 offset"))?,
+                offset => offset as u64,
+            };
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(&rest[46..46 + name_len]).into_owned(),
+                compression: u16_at(rest, 10),
+                compressed_size,
+                header_offset,
+            });
+            rest = &rest[len..];
+        }
+        Ok(entries)
+    }
+
+    /// Entry count, size and offset of the central directory, from the ZIP64
+    /// end of central directory when the classic one is out of range.
+    async fn locate_central_directory(&mut self) -> io::Result<(u64, u64, u64)> {
+        let len = self.file.seek(SeekFrom::End(0)).await?;
+        // the end of central directory is followed by a comment of at most
+        // u16::MAX bytes
+        let tail_len = len.min((END_OF_CENTRAL_DIRECTORY_LEN + u16::MAX as usize) as u64);
+        let mut tail = vec![0u8; tail_len as usize];
+        self.file.seek(SeekFrom::Start(len - tail_len)).await?;
+        self.file.read_exact(&mut tail).await?;
+        let end = (0..(tail.len() + 1).saturating_sub(END_OF_CENTRAL_DIRECTORY_LEN))
+            .rev()
+            .find(|position| u32_at(&tail[*position..], 0) == END_OF_CENTRAL_DIRECTORY)
+            .ok_or_else(|| invalid("No end of central directory"))?;
+        let record = &tail[end..];
+        let count = u16_at(record, 10);
+        let size = u32_at(record, 12);
+        let offset = u32_at(record, 16);
+        if count != u16::MAX && size != ZIP64_MARKER && offset != ZIP64_MARKER {
+            return fit_central_directory(len, count as u64, size as u64, offset as u64);
+        }
+        let locator = end
+            .checked_sub(20)
+            .map(|position| &tail[position..end])
+            .filter(|locator| u32_at(locator, 0) == ZIP64_LOCATOR)
+            .ok_or_else(|| invalid("No ZIP64 end of central directory locator"))?;
+        let mut record = [0u8; 56];
+        self.file.seek(SeekFrom::Start(u64_at(locator, 8))).await?;
+        self.file.read_exact(&mut record).await?;
+        if u32_at(&record, 0) != ZIP64_END_OF_CENTRAL_DIRECTORY {
+            return Err(invalid("No ZIP64 end of central directory"));
+        }
+        fit_central_directory(
+            len,
+            u64_at(&record, 32),
+            u64_at(&record, 40),
+            u64_at(&record, 48),
+        )
+    }
+}
+
+/// Checks that a central directory of `count` entries and `size` bytes at
+/// `offset` lies within an archive of `len` bytes, before it is allocated.
+fn fit_central_directory(
+    len: u64,
+    count: u64,
+    size: u64,
+    offset: u64,
+) -> io::Result<(u64, u64, u64)> {
+    let fits = offset.checked_add(size).is_some_and(|end| end <= len);
+    // every central directory header takes at least 46 bytes
+    if !fits || count > size / 46 {
+        return Err(invalid("Central directory does not fit in the archive"));
+    }
+    Ok((count, size, offset))
+}
+
+impl Drop for SeekableZip {
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.path);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(tokio::fs::remove_file(path));
+            }
+            Err(_) => {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Values of the ZIP64 extra field among `extra` fields.
+fn zip64_fields(mut extra: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    while extra.len() >= 4 {
+        let len = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+        if u16_at(extra, 0) == ZIP64_EXTRA_FIELD {
+            return extra[4..4 + len].chunks_exact(8).map(u64_le);
+        }
+        extra = &extra[4 + len..];
+    }
+    [].chunks_exact(8).map(u64_le)
+}
+
+fn u64_le(bytes: &[u8]) -> u64 {
+    u64_at(bytes, 0)
+}
+
+fn u16_at(bytes: &[u8], position: usize) -> u16 {
+    u16::from_le_bytes([bytes[position], bytes[position + 1]])
+}
+
+fn u32_at(bytes: &[u8], position: usize) -> u32 {
+    u32::from_le_bytes(bytes[position..position + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], position: usize) -> u64 {
+    u64::from_le_bytes(bytes[position..position + 8].try_into().unwrap())
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Zip of stored entries, ones flagged `zip64` have every size and offset
+    /// in ZIP64 fields, like zips of more than 4 GiB have them.
+    pub fn stored_zip(entries: &[(&str, &str, bool)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut offsets = Vec::new();
+        for (name, data, zip64) in entries {
+            let size = data.len() as u64;
+            offsets.push(archive.len() as u64);
+            archive.extend(LOCAL_HEADER.to_le_bytes());
+            archive.extend([45, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            if *zip64 {
+                archive.extend([0xff; 8]);
+            } else {
+                archive.extend((size as u32).to_le_bytes());
+                archive.extend((size as u32).to_le_bytes());
+            }
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend(if *zip64 { 20u16 } else { 0 }.to_le_bytes());
+            archive.extend(name.as_bytes());
+            if *zip64 {
+                archive.extend(ZIP64_EXTRA_FIELD.to_le_bytes());
+                archive.extend(16u16.to_le_bytes());
+                archive.extend(size.to_le_bytes());
+                archive.extend(size.to_le_bytes());
+            }
+            archive.extend(data.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u64;
+        for ((name, data, zip64), offset) in entries.iter().zip(offsets) {
+            let size = data.len() as u64;
+            archive.extend(CENTRAL_HEADER.to_le_bytes());
+            archive.extend([45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            if *zip64 {
+                archive.extend([0xff; 8]);
+            } else {
+                archive.extend((size as u32).to_le_bytes());
+                archive.extend((size as u32).to_le_bytes());
+            }
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend(if *zip64 { 28u16 } else { 0 }.to_le_bytes());
+            archive.extend([0; 10]);
+            if *zip64 {
+                archive.extend([0xff; 4]);
+            } else {
+                archive.extend((offset as u32).to_le_bytes());
+            }
+            archive.extend(name.as_bytes());
+            if *zip64 {
+                archive.extend(ZIP64_EXTRA_FIELD.to_le_bytes());
+                archive.extend(24u16.to_le_bytes());
+                archive.extend(size.to_le_bytes());
+                archive.extend(size.to_le_bytes());
+                archive.extend(offset.to_le_bytes());
+            }
+        }
+        let directory_size = archive.len() as u64 - directory_offset;
+
+        let count = entries.len() as u64;
+        let end_offset = archive.len() as u64;
+        archive.extend(ZIP64_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        archive.extend(44u64.to_le_bytes());
+        archive.extend([45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        archive.extend(count.to_le_bytes());
+        archive.extend(count.to_le_bytes());
+        archive.extend(directory_size.to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend(ZIP64_LOCATOR.to_le_bytes());
+        archive.extend(0u32.to_le_bytes());
+        archive.extend(end_offset.to_le_bytes());
+        archive.extend(1u32.to_le_bytes());
+        archive.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        archive.extend([0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        archive.extend([0xff; 8]);
+        archive.extend([0, 0]);
+        archive
+    }
+
+    #[actix_web::test]
+    async fn read_zip64_entry() {
+        let text = "id,side,price,timestamp\n1,buy,20180.5,1663027200557\n";
+        let archive = stored_zip(&[("trades.csv", text, true)]);
+        let mut zip = SeekableZip::spool(0, &mut archive.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(
+            zip.entries()
+                .iter()
+                .map(|entry| entry.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["trades.csv"]
+        );
+        let mut content = String::new();
+        zip.entry_reader(0)
+            .await
+            .unwrap()
+            .read_to_string(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, text);
+        let path = zip.path.clone();
+        drop(zip);
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while path.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("Spooled zip was not removed");
+    }
+
+    #[actix_web::test]
+    async fn read_rest_of_zip() {
+        let archive = stored_zip(&[
+            ("2022-09-13.csv", "id\n1\n", false),
+            ("2022-09-14.csv", "id\n2\n", true),
+        ]);
+        let offset = archive
+            .windows(4)
+            .rposition(|window| window == LOCAL_HEADER.to_le_bytes())
+            .unwrap();
+
+        let mut zip = SeekableZip::spool(offset as u64, &mut &archive[offset..])
+            .await
+            .unwrap();
+        assert_eq!(zip.entries()[1].name, "2022-09-14.csv");
+        assert!(zip.entry_reader(0).await.is_err());
+        let mut content = String::new();
+        zip.entry_reader(1)
+            .await
+            .unwrap()
+            .read_to_string(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, "id\n2\n");
+    }
+
+    #[actix_web::test]
+    async fn reject_oversized_central_directory() {
+        let mut archive = stored_zip(&[("trades.csv", "id\n", true)]);
+        // size of the central directory in the ZIP64 end of central directory
+        let field = archive.len() - 22 - 20 - 56 + 40;
+        archive[field..field + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let error = SeekableZip::spool(0, &mut archive.as_slice())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
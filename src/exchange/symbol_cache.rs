@@ -0,0 +1,308 @@
+use crate::exchange::registry::{Exchange, ExchangeError, ExchangeRegistry};
+use crate::exchange::trade_reader::{ArchiveClient, Market, TradePair};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Listing every symbol takes a few bucket pages, new symbols show up daily.
+pub const SYMBOLS_MAX_AGE: Duration = Duration::from_secs(6 * 60 * 60);
+
+type SymbolsKey = (String, Market);
+
+/// Symbol lists per exchange and market, shared by all workers. Lists asked
+/// for once are refreshed every `max_age` by [`SymbolCache::refresh_periodically`],
+/// a list still older than that, e.g. after a failed refresh, is served while
+/// it is refreshed in the background. Requests for a list not cached yet wait
+/// for a single fetch of it.
+pub struct SymbolCache {
+    max_age: Duration,
+    entries: Mutex<HashMap<SymbolsKey, CachedSymbols>>,
+    fetching: Mutex<HashMap<SymbolsKey, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+struct CachedSymbols {
+    symbols: Arc<Vec<String>>,
+    fetched_at: Instant,
+    refreshing: bool,
+}
+
+impl SymbolCache {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            entries: Mutex::new(HashMap::new()),
+            fetching: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn symbols(
+        self: Arc<Self>,
+        exchange: &dyn Exchange,
+        client: &ArchiveClient,
+        market: Market,
+    ) -> Result<Arc<Vec<String>>, ExchangeError> {
+        let key = (exchange.name().to_string(), market);
+        if let Some(symbols) = self.cached(exchange, client, &key) {
+            return Ok(symbols);
+        }
+        let fetching = self
+            .fetching
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let _fetching = fetching.lock().await;
+        // fetched by the request this one waited for
+        if let Some(symbols) = self.cached(exchange, client, &key) {
+            return Ok(symbols);
+        }
+        let fetched = exchange.symbols(client, market).await;
+        self.store(key, fetched)
+    }
+
+    /// Cached list of `key`, refreshed in the background when stale.
+    fn cached(
+        self: &Arc<Self>,
+        exchange: &dyn Exchange,
+        client: &ArchiveClient,
+        key: &SymbolsKey,
+    ) -> Option<Arc<Vec<String>>> {
+        let mut entries = self.entries.lock().unwrap();
+        let cached = entries.get_mut(key)?;
+        if cached.fetched_at.elapsed() >= self.max_age && !cached.refreshing {
+            cached.refreshing = true;
+            let cache = self.clone();
+            let key = key.clone();
+            let fetch = exchange.symbols(client, key.1);
+            debug!("Refreshing symbols of {} {}", key.0, key.1);
+            tokio::spawn(async move {
+                let fetched = fetch.await;
+                let _refreshed = cache.store(key, fetched);
+            });
+        }
+        Some(cached.symbols.clone())
+    }
+
+    /// Refreshes every cached list each `max_age`, runs until the runtime stops.
+    pub async fn refresh_periodically(
+        self: Arc<Self>,
+        exchanges: ExchangeRegistry,
+        client: ArchiveClient,
+    ) {
+        let mut interval = tokio::time::interval(self.max_age);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let keys = {
+                let mut entries = self.entries.lock().unwrap();
+                entries
+                    .iter_mut()
+                    .filter(|(_, cached)| !cached.refreshing)
+                    .map(|(key, cached)| {
+                        cached.refreshing = true;
+                        key.clone()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            for key in keys {
+                let fetched = match exchanges.get(&key.0) {
+                    Some(exchange) => {
+                        debug!("Refreshing symbols of {} {}", key.0, key.1);
+                        exchange.symbols(&client, key.1).await
+                    }
+                    None => Err(ExchangeError::NotFound),
+                };
+                let _refreshed = self.store(key, fetched);
+            }
+        }
+    }
+
+    fn store(
+        &self,
+        key: SymbolsKey,
+        fetched: Result<Vec<TradePair>, ExchangeError>,
+    ) -> Result<Arc<Vec<String>>, ExchangeError> {
+        let mut entries = self.entries.lock().unwrap();
+        match fetched {
+            Ok(pairs) => {
+                let mut symbols = pairs
+                    .iter()
+                    .map(|pair| pair.to_string())
+                    .collect::<Vec<_>>();
+                symbols.sort();
+                symbols.dedup();
+                let symbols = Arc::new(symbols);
+                entries.insert(
+                    key,
+                    CachedSymbols {
+                        symbols: symbols.clone(),
+                        fetched_at: Instant::now(),
+                        refreshing: false,
+                    },
+                );
+                Ok(symbols)
+            }
+            Err(err) => {
+                if let Some(cached) = entries.get_mut(&key) {
+                    warn!("Could not refresh symbols of {} {}: {}", key.0, key.1, err);
+                    cached.refreshing = false;
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::http_pool::HttpPool;
+    use crate::exchange::registry::DatasetRequest;
+    use crate::exchange::trade_reader::CreateStream;
+    use futures::future;
+    use futures::future::BoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Listing {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Exchange for Listing {
+        fn name(&self) -> &'static str {
+            "listing"
+        }
+
+        fn markets(&self) -> &'static [Market] {
+            &[Market::Spot]
+        }
+
+        fn sample_trade_every(&self) -> usize {
+            1
+        }
+
+        fn trades<'a>(
+            &'a self,
+            _client: &'a ArchiveClient,
+            _request: &'a DatasetRequest,
+            _sample_every_n_trade: usize,
+        ) -> BoxFuture<'a, Result<CreateStream, ExchangeError>> {
+            Box::pin(future::ready(Err(ExchangeError::NotFound)))
+        }
+
+        fn symbols(
+            &self,
+            _client: &ArchiveClient,
+            _market: Market,
+        ) -> BoxFuture<'static, Result<Vec<TradePair>, ExchangeError>> {
+            let calls = self.calls.clone();
+            Box::pin(async move {
+                let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::task::yield_now().await;
+                let mut pairs = vec![TradePair::new("ETH", "USDT"), TradePair::new("BTC", "USDT")];
+                if call > 1 {
+                    pairs.push(TradePair::new("SOL", "USDT"));
+                }
+                Ok(pairs)
+            })
+        }
+    }
+
+    #[actix_web::test]
+    async fn serve_cached_symbols() {
+        let exchange = Listing {
+            calls: Arc::new(AtomicUsize::new(0)),
+        };
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let cache = Arc::new(SymbolCache::new(Duration::from_secs(60)));
+        for _ in 0..2 {
+            let symbols = cache
+                .clone()
+                .symbols(&exchange, &client, Market::Spot)
+                .await
+                .unwrap();
+            assert_eq!(*symbols, vec!["BTC-USDT", "ETH-USDT"]);
+        }
+        assert_eq!(exchange.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn fetch_cold_symbols_once() {
+        let exchange = Listing {
+            calls: Arc::new(AtomicUsize::new(0)),
+        };
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let cache = Arc::new(SymbolCache::new(Duration::from_secs(60)));
+        let (first, second) = futures::join!(
+            cache.clone().symbols(&exchange, &client, Market::Spot),
+            cache.clone().symbols(&exchange, &client, Market::Spot),
+        );
+        assert_eq!(first.unwrap(), second.unwrap());
+        assert_eq!(exchange.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn refresh_stale_symbols_in_background() {
+        let exchange = Listing {
+            calls: Arc::new(AtomicUsize::new(0)),
+        };
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let cache = Arc::new(SymbolCache::new(Duration::ZERO));
+        cache
+            .clone()
+            .symbols(&exchange, &client, Market::Spot)
+            .await
+            .unwrap();
+        let stale = cache
+            .clone()
+            .symbols(&exchange, &client, Market::Spot)
+            .await
+            .unwrap();
+        assert_eq!(stale.len(), 2);
+        let fresh = wait_for_symbols(&cache, 3).await;
+        assert_eq!(*fresh, vec!["BTC-USDT", "ETH-USDT", "SOL-USDT"]);
+        assert_eq!(exchange.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn refresh_symbols_periodically() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let exchange = Listing {
+            calls: calls.clone(),
+        };
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let cache = Arc::new(SymbolCache::new(Duration::from_millis(20)));
+        cache
+            .clone()
+            .symbols(&exchange, &client, Market::Spot)
+            .await
+            .unwrap();
+        let exchanges = ExchangeRegistry::empty().with(Listing { calls });
+        actix_web::rt::spawn(cache.clone().refresh_periodically(exchanges, client));
+
+        let fresh = wait_for_symbols(&cache, 3).await;
+        assert_eq!(*fresh, vec!["BTC-USDT", "ETH-USDT", "SOL-USDT"]);
+    }
+
+    /// Waits until the cached spot symbols of [`Listing`] have `len` entries.
+    async fn wait_for_symbols(cache: &SymbolCache, len: usize) -> Arc<Vec<String>> {
+        let key = ("listing".to_string(), Market::Spot);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let cached = cache
+                    .entries
+                    .lock()
+                    .unwrap()
+                    .get(&key)
+                    .map(|cached| cached.symbols.clone());
+                if let Some(symbols) = cached.filter(|symbols| symbols.len() == len) {
+                    return symbols;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("Symbols were not refreshed")
+    }
+}
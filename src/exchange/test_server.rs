@@ -0,0 +1,13 @@
+use actix_web::{web, App, HttpServer};
+
+/// Serves `routes` on a free local port for the rest of the test and returns
+/// its base url, e.g. `http://127.0.0.1:41234`.
+pub fn serve(routes: impl Fn(&mut web::ServiceConfig) + Clone + Send + 'static) -> String {
+    let server = HttpServer::new(move || App::new().configure(routes.clone()))
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let base_url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+    base_url
+}
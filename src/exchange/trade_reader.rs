@@ -1,42 +1,156 @@
+use crate::exchange::archive_cache::ArchiveCache;
+use crate::exchange::checksum::{
+    checksum_mismatch, fetch_checksum, ChecksumMismatch, ChecksumReader,
+};
+use crate::exchange::download::{resumable_body, send_retrying, RetryPolicy};
+use crate::exchange::http_pool::{forward, HttpPool};
+use crate::exchange::local_mirror::{file_url_path, open_local_archive, LocalMirror};
+use crate::exchange::proxy::Proxy;
+use crate::exchange::seekable_zip::SeekableZip;
+use crate::exchange::upstream::Upstreams;
+use actix_http::header;
 use actix_http::StatusCode;
+use async_compression::tokio::bufread::GzipDecoder;
 use async_zip::error::ZipError;
 use async_zip::read::stream::ZipFileReader;
-use async_zip::read::ZipEntryReader;
-use awc::error::SendRequestError;
-use futures::future::Ready;
-use futures::{future, Stream, StreamExt, TryStreamExt};
-use log::error;
+use awc::error::PayloadError;
+use chrono::{Datelike, NaiveDate};
+use futures::{future, Stream, StreamExt};
+use log::{debug, warn};
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Formatter};
 use std::future::Future;
-use std::num::{ParseFloatError, ParseIntError};
+use std::num::ParseIntError;
+use std::ops::Range;
 use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
 use thiserror::Error;
 use tokio::io;
-use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, ReadBuf};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{mpsc, oneshot};
-use tokio::task::spawn_local;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::io::StreamReader;
 
-pub type ReadResult = Result<Trade, TradeReaderError>;
+pub type ReadResult<T = Trade> = Result<T, TradeReaderError>;
 
-type CreateStream = Result<Pin<Box<dyn Stream<Item = ReadResult>>>, TradeReaderError>;
+pub type CreateStream<T = Trade> =
+    Result<Pin<Box<dyn Stream<Item = ReadResult<T>> + Send>>, TradeReaderError>;
 
-type CreateStreamFuture = Pin<Box<dyn Future<Output = CreateStream>>>;
+pub type CreateStreamFuture<T = Trade> = Pin<Box<dyn Future<Output = CreateStream<T>> + Send>>;
 
-pub trait TradeReader {
+pub trait TradeReader<T = Trade> {
     #[allow(clippy::needless_lifetimes)]
-    fn stream(self, sample_every_n_trade: usize) -> CreateStreamFuture;
+    fn stream(self, sample_every_n_trade: usize) -> CreateStreamFuture<T>;
 }
 
+/// Prices and quantities keep the decimal places of the archive they were
+/// read from.
 #[derive(Debug)]
 pub struct Trade {
     pub id: u64,
     pub side: TradeSide,
-    pub price: f64,
-    pub timestamp: i64,
+    pub price: Decimal,
+    /// Traded amount of the base asset.
+    pub quantity: Decimal,
+    /// Traded value in the quote asset.
+    pub quote_quantity: Decimal,
+    pub timestamp: Timestamp,
+}
+
+/// Unit of a raw Unix timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampUnit {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl TimestampUnit {
+    /// Guesses the unit from the number of digits, which is unambiguous for
+    /// dates between 1973 and 5138.
+    pub fn detect(raw: i64) -> Self {
+        match raw.unsigned_abs() {
+            0..=99_999_999_999 => TimestampUnit::Seconds,
+            100_000_000_000..=99_999_999_999_999 => TimestampUnit::Millis,
+            100_000_000_000_000..=99_999_999_999_999_999 => TimestampUnit::Micros,
+            _ => TimestampUnit::Nanos,
+        }
+    }
+}
+
+/// Unix timestamp kept in microseconds, whatever unit the source used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+    /// Saturates at the limits of `i64` microseconds, which are far beyond any
+    /// trade.
+    pub fn new(raw: i64, unit: TimestampUnit) -> Self {
+        Timestamp(match unit {
+            TimestampUnit::Seconds => raw.saturating_mul(1_000_000),
+            TimestampUnit::Millis => raw.saturating_mul(1_000),
+            TimestampUnit::Micros => raw,
+            TimestampUnit::Nanos => raw / 1_000,
+        })
+    }
+
+    /// Timestamp of a source which may use any unit, e.g. Binance spot
+    /// archives switching from milliseconds to microseconds.
+    pub fn detect(raw: i64) -> Self {
+        Self::new(raw, TimestampUnit::detect(raw))
+    }
+
+    pub fn from_millis(millis: i64) -> Self {
+        Self::new(millis, TimestampUnit::Millis)
+    }
+
+    /// Start of the UTC day `from` until the end of the UTC day `to`.
+    pub fn days(from: NaiveDate, to: NaiveDate) -> Range<Self> {
+        let midnight = |date: NaiveDate| date.and_hms(0, 0, 0).timestamp_millis();
+        Self::from_millis(midnight(from))..Self::from_millis(midnight(to) + 86_400_000)
+    }
+
+    pub fn as_micros(&self) -> i64 {
+        self.0
+    }
+
+    /// Canonical unit of the dataset output.
+    pub fn as_millis(&self) -> i64 {
+        self.0.div_euclid(1_000)
+    }
+}
+
+/// Timestamp used to trim rows to a time window.
+pub trait Timestamped {
+    fn timestamp(&self) -> Timestamp;
+}
+
+impl Timestamped for Trade {
+    fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+}
+
+/// OHLCV bar starting at `open_time`.
+#[derive(Debug, PartialEq)]
+pub struct Candle {
+    pub open_time: Timestamp,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+}
+
+impl Timestamped for Candle {
+    fn timestamp(&self) -> Timestamp {
+        self.open_time
+    }
 }
 
 #[derive(serde::Serialize, PartialEq, Debug, Clone, Copy)]
@@ -47,10 +161,188 @@ pub enum TradeSide {
     Sell,
 }
 
-#[derive(Debug)]
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Market {
+    #[serde(rename = "spot")]
+    Spot,
+    #[serde(rename = "usdm")]
+    UsdMFutures,
+    #[serde(rename = "coinm")]
+    CoinMFutures,
+}
+
+impl Display for Market {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Market::Spot => "spot",
+            Market::UsdMFutures => "usdm",
+            Market::CoinMFutures => "coinm",
+        })
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DatasetType {
+    #[default]
+    #[serde(rename = "trades")]
+    Trades,
+    /// Trades aggregated by taker order and price, several times smaller than
+    /// raw trades.
+    #[serde(rename = "aggTrades")]
+    AggTrades,
+}
+
+impl Display for DatasetType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DatasetType::Trades => "trades",
+            DatasetType::AggTrades => "aggTrades",
+        })
+    }
+}
+
+/// Day or calendar month of data, the periods archives are published for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchivePeriod {
+    Daily(NaiveDate),
+    Monthly { year: i32, month: u32 },
+}
+
+impl ArchivePeriod {
+    /// Days covered by the period, whose daily archives hold the same rows.
+    pub fn days(&self) -> Vec<NaiveDate> {
+        match *self {
+            ArchivePeriod::Daily(date) => vec![date],
+            ArchivePeriod::Monthly { year, month } => NaiveDate::from_ymd_opt(year, month, 1)
+                .into_iter()
+                .flat_map(|first| first.iter_days())
+                .take_while(|date| date.month() == month)
+                .collect(),
+        }
+    }
+}
+
+impl Display for ArchivePeriod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchivePeriod::Daily(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            ArchivePeriod::Monthly { year, month } => write!(f, "{:04}-{:02}", year, month),
+        }
+    }
+}
+
+/// Parses `YYYY-MM-DD` as a daily and `YYYY-MM` as a monthly period.
+impl FromStr for ArchivePeriod {
+    type Err = chrono::ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            Ok(date) => Ok(ArchivePeriod::Daily(date)),
+            Err(err) => NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d")
+                .map(|date| ArchivePeriod::Monthly {
+                    year: date.year(),
+                    month: date.month(),
+                })
+                .map_err(|_| err),
+        }
+    }
+}
+
+/// Length of a candle.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KlineInterval {
+    #[serde(rename = "1s")]
+    Second1,
+    #[default]
+    #[serde(rename = "1m")]
+    Minute1,
+    #[serde(rename = "3m")]
+    Minute3,
+    #[serde(rename = "5m")]
+    Minute5,
+    #[serde(rename = "15m")]
+    Minute15,
+    #[serde(rename = "30m")]
+    Minute30,
+    #[serde(rename = "1h")]
+    Hour1,
+    #[serde(rename = "2h")]
+    Hour2,
+    #[serde(rename = "4h")]
+    Hour4,
+    #[serde(rename = "6h")]
+    Hour6,
+    #[serde(rename = "8h")]
+    Hour8,
+    #[serde(rename = "12h")]
+    Hour12,
+    #[serde(rename = "1d")]
+    Day1,
+    #[serde(rename = "3d")]
+    Day3,
+    #[serde(rename = "1w")]
+    Week1,
+    #[serde(rename = "1mo")]
+    Month1,
+}
+
+impl Display for KlineInterval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            KlineInterval::Second1 => "1s",
+            KlineInterval::Minute1 => "1m",
+            KlineInterval::Minute3 => "3m",
+            KlineInterval::Minute5 => "5m",
+            KlineInterval::Minute15 => "15m",
+            KlineInterval::Minute30 => "30m",
+            KlineInterval::Hour1 => "1h",
+            KlineInterval::Hour2 => "2h",
+            KlineInterval::Hour4 => "4h",
+            KlineInterval::Hour6 => "6h",
+            KlineInterval::Hour8 => "8h",
+            KlineInterval::Hour12 => "12h",
+            KlineInterval::Day1 => "1d",
+            KlineInterval::Day3 => "3d",
+            KlineInterval::Week1 => "1w",
+            KlineInterval::Month1 => "1mo",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contract {
+    Perpetual,
+    Delivery(NaiveDate),
+}
+
+impl Display for Contract {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Contract::Perpetual => f.write_str("PERP"),
+            Contract::Delivery(expiry) => write!(f, "{}", expiry.format("%y%m%d")),
+        }
+    }
+}
+
+impl FromStr for Contract {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "PERP" | "SWAP" => Ok(Contract::Perpetual),
+            expiry if expiry.len() == 6 => NaiveDate::parse_from_str(expiry, "%y%m%d")
+                .map(Contract::Delivery)
+                .map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct TradePair {
     pub first: String,
     pub second: String,
+    pub contract: Option<Contract>,
 }
 
 impl TradePair {
@@ -58,192 +350,1034 @@ impl TradePair {
         Self {
             first: first.into(),
             second: second.into(),
+            contract: None,
         }
     }
+
+    pub fn with_contract(mut self, contract: Contract) -> Self {
+        self.contract = Some(contract);
+        self
+    }
 }
 
 impl Display for TradePair {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}-{}", self.first, self.second)
+        write!(f, "{}-{}", self.first, self.second)?;
+        if let Some(contract) = &self.contract {
+            write!(f, "-{}", contract)?;
+        }
+        Ok(())
     }
 }
 
+/// Parses `FIRST-SECOND` with an optional contract suffix, e.g. `BTC-USD-PERP`,
+/// OKX-like `BTC-USDT-SWAP`, `BTC-USD-221230` or Binance-like `BTC-USD_PERP`.
 impl FromStr for TradePair {
     type Err = ();
 
     fn from_str(text: &str) -> Result<Self, Self::Err> {
-        let mut split = text.split('-');
-        if let (Some(first), Some(second)) = (split.next(), split.next()) {
-            Ok(TradePair {
-                first: first.to_string(),
-                second: second.to_string(),
+        let mut split = text.split(['-', '_']);
+        let (first, second) = match (split.next(), split.next()) {
+            (Some(first), Some(second)) if !first.is_empty() && !second.is_empty() => {
+                (first, second)
+            }
+            _ => return Err(()),
+        };
+        let contract = split.next().map(Contract::from_str).transpose()?;
+        if split.next().is_some() {
+            return Err(());
+        }
+        Ok(TradePair {
+            first: first.to_string(),
+            second: second.to_string(),
+            contract,
+        })
+    }
+}
+
+/// Parses rows laid out as `columns`. Rows of archives starting with a
+/// header are rearranged into that layout by column name first, so added or
+/// reordered columns do not shift the fields read by `parse`.
+pub struct TradeParser<T = Trade> {
+    /// Names of the expected columns, matched against headers regardless of
+    /// case and underscores, e.g. `quote_qty` matches `quoteQty`.
+    pub columns: &'static [&'static str],
+    pub parse: fn(String) -> Result<T, ParseTradeError>,
+}
+
+impl<T> TradeParser<T> {
+    pub const fn new(
+        columns: &'static [&'static str],
+        parse: fn(String) -> Result<T, ParseTradeError>,
+    ) -> Self {
+        Self { columns, parse }
+    }
+
+    /// Layout of rows under `header`.
+    fn row_layout(&self, header: &str) -> Result<RowLayout, ParseTradeError> {
+        let names = header
+            .trim_start_matches('\u{feff}')
+            .trim_end()
+            .split(',')
+            .map(column_key)
+            .collect::<Vec<_>>();
+        let positions = self
+            .columns
+            .iter()
+            .map(|column| {
+                let key = column_key(column);
+                names
+                    .iter()
+                    .position(|name| *name == key)
+                    .ok_or(ParseTradeError::MissingColumn(column))
             })
-        } else {
-            Err(())
+            .collect::<Result<Vec<_>, _>>()?;
+        match positions
+            .iter()
+            .enumerate()
+            .all(|(index, position)| index == *position)
+        {
+            true => Ok(RowLayout::Expected),
+            false => Ok(RowLayout::Mapped(positions)),
         }
     }
 }
 
-type TradeParser = fn(String) -> Result<Trade, ParseTradeError>;
+/// Layout of the rows of one archive.
+enum RowLayout {
+    Expected,
+    /// Positions of the expected columns.
+    Mapped(Vec<usize>),
+    /// The header lacks expected columns, so the rows can not be read.
+    Invalid,
+}
+
+impl<T> Clone for TradeParser<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TradeParser<T> {}
+
+/// Header name without the translation some exchanges append after `/`,
+/// e.g. `trade_id/交易id`.
+fn column_key(name: &str) -> String {
+    name.split('/')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .filter(|char| *char != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Headers are told apart from rows by content, as not every archive has
+/// one: a line without any numeric field is a header.
+fn is_csv_header(line: &str) -> bool {
+    !line.trim().is_empty()
+        && line
+            .trim_end()
+            .split(',')
+            .all(|field| field.trim().parse::<f64>().is_err())
+}
+
+/// `row` with the fields at `positions`.
+fn rearrange_row(row: &str, positions: &[usize]) -> String {
+    let fields = row.trim_end().split(',').collect::<Vec<_>>();
+    positions
+        .iter()
+        .map(|position| fields.get(*position).copied().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub type LinesStream = Pin<Box<dyn Stream<Item = io::Result<String>> + Send>>;
+
+type LinesStreamFuture = Pin<Box<dyn Future<Output = Result<LinesStream, ZipReaderError>> + Send>>;
 
-pub struct ZipCsvTradeReader<R: AsyncRead + Unpin + Sized> {
-    zip_reader: ZipLinesReader<R>,
-    parse_trade: TradeParser,
+/// Source of raw CSV lines, every `sample_every_line`-th line is yielded.
+pub trait LinesReader: Send {
+    fn stream(self: Box<Self>, sample_every_line: usize) -> LinesStreamFuture;
 }
 
-impl<R> ZipCsvTradeReader<R>
-where
-    R: AsyncRead + Unpin + Sized,
-{
-    pub fn new(zip_reader: ZipLinesReader<R>, parse_trade: TradeParser) -> Self {
+pub struct CsvTradeReader<T = Trade> {
+    lines_reader: Box<dyn LinesReader>,
+    parse_trade: TradeParser<T>,
+}
+
+impl<T> CsvTradeReader<T> {
+    pub fn new(lines_reader: Box<dyn LinesReader>, parse_trade: TradeParser<T>) -> Self {
         Self {
-            zip_reader,
+            lines_reader,
             parse_trade,
         }
     }
 
-    fn is_csv_header((index, res): &(usize, io::Result<String>)) -> Ready<bool> {
-        future::ready(res.is_ok() && *index == 0)
-    }
-
     fn parse_csv_row(
-        parse_trade: TradeParser,
+        parse_trade: TradeParser<T>,
+        layout: &RowLayout,
         read_next_result: io::Result<String>,
-    ) -> Result<Trade, TradeReaderError> {
+    ) -> Result<T, TradeReaderError> {
         read_next_result
-            .map(|line| (parse_trade)(line).map_err(TradeReaderError::Parse))
-            .map_err(TradeReaderError::IO)
+            .map(|line| match layout {
+                RowLayout::Mapped(positions) => rearrange_row(&line, positions),
+                _ => line,
+            })
+            .map(|line| (parse_trade.parse)(line).map_err(TradeReaderError::Parse))
+            .map_err(Self::read_error)
             .and_then(|flatten| flatten)
     }
+
+    fn open_error(err: ZipReaderError) -> TradeReaderError {
+        match err {
+            ZipReaderError::Spooled(err) => match checksum_mismatch(&err) {
+                Some(_) => Self::read_error(err),
+                None => TradeReaderError::ZipReader(ZipReaderError::Spooled(err)),
+            },
+            other => TradeReaderError::ZipReader(other),
+        }
+    }
+
+    fn read_error(err: io::Error) -> TradeReaderError {
+        match checksum_mismatch(&err) {
+            Some(mismatch) => TradeReaderError::HttpZipReader(
+                mismatch.url.clone(),
+                HttpZipReaderError::ChecksumMismatch(mismatch.clone()),
+            ),
+            None => TradeReaderError::IO(err),
+        }
+    }
 }
 
-impl<R> TradeReader for ZipCsvTradeReader<R>
-where
-    R: AsyncRead + Unpin + Sized + 'static,
-{
-    fn stream(self, sample_every_n_trade: usize) -> CreateStreamFuture {
+impl<T: Send + 'static> TradeReader<T> for CsvTradeReader<T> {
+    fn stream(self, sample_every_n_trade: usize) -> CreateStreamFuture<T> {
         let fut = async move {
-            let boxed: Result<Pin<Box<dyn Stream<Item = ReadResult>>>, TradeReaderError> = self
-                .zip_reader
+            let boxed: CreateStream<T> = self
+                .lines_reader
                 .stream(sample_every_n_trade)
                 .await
-                .map(move |stream| -> Pin<Box<dyn Stream<Item = ReadResult>>> {
-                    let stream = stream
-                        .enumerate()
-                        .skip_while(Self::is_csv_header)
-                        .map(|(_, res)| res)
-                        .map(move |read_next_result: io::Result<String>| {
-                            Self::parse_csv_row(self.parse_trade, read_next_result)
-                        });
-                    Box::pin(stream)
-                })
-                .map_err(TradeReaderError::ZipReader);
+                .map(
+                    move |stream| -> Pin<Box<dyn Stream<Item = ReadResult<T>> + Send>> {
+                        let parse_trade = self.parse_trade;
+                        // every entry of a multi-file zip may start with a header
+                        let stream = stream
+                            .scan(RowLayout::Expected, move |layout, res| {
+                                let item = match (&layout, res) {
+                                    (RowLayout::Invalid, _) => return future::ready(None),
+                                    (_, Ok(line)) if is_csv_header(&line) => {
+                                        match parse_trade.row_layout(&line) {
+                                            Ok(header_layout) => {
+                                                *layout = header_layout;
+                                                None
+                                            }
+                                            Err(err) => {
+                                                *layout = RowLayout::Invalid;
+                                                Some(Err(TradeReaderError::Parse(err)))
+                                            }
+                                        }
+                                    }
+                                    (_, res) => Some(Self::parse_csv_row(parse_trade, layout, res)),
+                                };
+                                future::ready(Some(item))
+                            })
+                            .filter_map(future::ready);
+                        Box::pin(stream)
+                    },
+                )
+                .map_err(Self::open_error);
             boxed
         };
         Box::pin(fut)
     }
 }
 
+/// Streams several archives one after another through [`CsvTradeReader`].
+/// The first archive is opened eagerly, so a missing dataset is reported up
+/// front; archives missing later on end up as errors inside the stream.
+pub struct CsvRangeTradeReader<T = Trade> {
+    client: ArchiveClient,
+    options: ArchiveOptions,
+    first: CsvTradeReader<T>,
+    rest_urls: Vec<ArchiveUrl>,
+    window: Option<Range<Timestamp>>,
+}
+
+impl<T> CsvRangeTradeReader<T> {
+    /// Drops rows with timestamps outside of `window`, for archives which are
+    /// not cut at the requested boundaries.
+    pub fn within(mut self, window: Range<Timestamp>) -> Self {
+        self.window = Some(window);
+        self
+    }
+}
+
+impl<T: Timestamped + Send + 'static> TradeReader<T> for CsvRangeTradeReader<T> {
+    fn stream(self, sample_every_n_trade: usize) -> CreateStreamFuture<T> {
+        let parse_trade = self.first.parse_trade;
+        let client = self.client;
+        let options = self.options;
+        let fut = async move {
+            let first = self.first.stream(sample_every_n_trade).await?;
+            let rest = futures::stream::iter(self.rest_urls)
+                .then(move |archive| {
+                    let client = client.clone();
+                    async move {
+                        let rows = archive_rows(
+                            &client,
+                            options,
+                            archive.url.clone(),
+                            parse_trade,
+                            sample_every_n_trade,
+                        )
+                        .await;
+                        match rows {
+                            Err(TradeReaderError::HttpZipReader(
+                                _,
+                                HttpZipReaderError::NotFound,
+                            )) if !archive.fallback.is_empty() => {
+                                debug!("{} is not published, reading its fallback", archive.url);
+                                Ok(urls_rows(
+                                    client,
+                                    options,
+                                    archive.fallback,
+                                    parse_trade,
+                                    sample_every_n_trade,
+                                ))
+                            }
+                            rows => rows,
+                        }
+                    }
+                })
+                .flat_map(rows_or_error);
+            let window = self.window;
+            let chained: Pin<Box<dyn Stream<Item = ReadResult<T>> + Send>> =
+                Box::pin(first.chain(rest).filter(move |result| {
+                    future::ready(match (result, &window) {
+                        (Ok(row), Some(window)) => window.contains(&row.timestamp()),
+                        _ => true,
+                    })
+                }));
+            Ok(chained)
+        };
+        Box::pin(fut)
+    }
+}
+
+/// Rows of the archive at `url`.
+async fn archive_rows<T: Send + 'static>(
+    client: &ArchiveClient,
+    options: ArchiveOptions,
+    url: String,
+    parse_trade: TradeParser<T>,
+    sample_every_n_trade: usize,
+) -> CreateStream<T> {
+    let lines_reader = http_lines_reader(client, options, &url)
+        .await
+        .map_err(|err| TradeReaderError::HttpZipReader(url, err))?;
+    CsvTradeReader::new(lines_reader, parse_trade)
+        .stream(sample_every_n_trade)
+        .await
+}
+
+/// Rows of the archives at `urls`, one archive after another.
+fn urls_rows<T: Send + 'static>(
+    client: ArchiveClient,
+    options: ArchiveOptions,
+    urls: Vec<String>,
+    parse_trade: TradeParser<T>,
+    sample_every_n_trade: usize,
+) -> Pin<Box<dyn Stream<Item = ReadResult<T>> + Send>> {
+    Box::pin(
+        futures::stream::iter(urls)
+            .then(move |url| {
+                let client = client.clone();
+                async move {
+                    archive_rows(&client, options, url, parse_trade, sample_every_n_trade).await
+                }
+            })
+            .flat_map(rows_or_error),
+    )
+}
+
+/// Rows of an opened archive, or the error it could not be opened with.
+fn rows_or_error<T: Send + 'static>(
+    archive: CreateStream<T>,
+) -> Pin<Box<dyn Stream<Item = ReadResult<T>> + Send>> {
+    match archive {
+        Ok(stream) => stream,
+        Err(err) => Box::pin(futures::stream::once(future::ready(Err(err)))),
+    }
+}
+
+/// Lines of every zip entry matching `entries`, one entry after another. The
+/// rest of the source is drained afterwards, so wrapping readers see all of
+/// its bytes. Zips with an entry the stream reader can not read, e.g. one with
+/// ZIP64 sizes, are spooled to a temp file from that entry on and read through
+/// their central directory instead.
 pub struct ZipLinesReader<R: AsyncRead + Unpin> {
-    zip: ZipFileReader<R>,
+    source: R,
+    entries: Option<&'static str>,
 }
 
-impl<R: AsyncRead + Unpin + 'static> ZipLinesReader<R> {
-    pub async fn stream(
-        mut self,
-        sample_every_line: usize,
-    ) -> Result<impl Stream<Item = io::Result<String>>, ZipReaderError> {
-        let (zip_result_writer, zip_result_reader) = oneshot::channel();
-        let (writer, receiver) = mpsc::channel(128);
-        // nwm kurwa entry_reader z zipa bierze referencje i przez to
-        // nie mozemy zwrocic narmalnie streama ktory uzywa entry_reader
-        // bo zip pojdzie poza scope i bd dropniety no ogulem nwm
-        spawn_local(async move {
-            let zip_result = self
-                .zip
-                .entry_reader()
+type ZipResultWriter = oneshot::Sender<Option<ZipReaderError>>;
+
+impl<R: AsyncRead + Unpin + Send + 'static> LinesReader for ZipLinesReader<R> {
+    fn stream(self: Box<Self>, sample_every_line: usize) -> LinesStreamFuture {
+        let source = self.source;
+        let entries = self.entries;
+        let fut = async move {
+            let (zip_result_writer, zip_result_reader) = oneshot::channel();
+            let (writer, receiver) = mpsc::channel(128);
+            // nwm kurwa entry_reader z zipa bierze referencje i przez to
+            // nie mozemy zwrocic narmalnie streama ktory uzywa entry_reader
+            // bo zip pojdzie poza scope i bd dropniety no ogulem nwm
+            tokio::spawn(async move {
+                let mut zip_result_writer = Some(zip_result_writer);
+                let mut source = Rewindable::new(source);
+                let skip = match stream_zip_entries(
+                    &mut source,
+                    entries,
+                    &writer,
+                    &mut zip_result_writer,
+                    sample_every_line,
+                )
                 .await
-                .map_err(ZipReaderError::Unzip)
-                .map(|reader_maybe| reader_maybe.ok_or(ZipReaderError::EmptyZip))
-                .and_then(|flatten| flatten);
-            match zip_result {
-                Ok(mut zip) => {
-                    let _ = zip_result_writer.send(None);
-                    Self::write_essa(writer, &mut zip, sample_every_line).await;
-                }
-                Err(err) => {
-                    let _ = zip_result_writer.send(Some(err));
-                }
+                {
+                    Ok(()) => return,
+                    Err(rejected) => {
+                        debug!("Could not stream zip ({}), spooling it", rejected.err);
+                        rejected.index
+                    }
+                };
+                let (offset, recent, source) = source.into_parts();
+                let mut source = std::io::Cursor::new(recent).chain(source);
+                spooled_zip_entries(
+                    &mut source,
+                    offset,
+                    skip,
+                    entries,
+                    &writer,
+                    &mut zip_result_writer,
+                    sample_every_line,
+                )
+                .await;
+            });
+            // the reader task only drops the writer without a result if it panicked
+            let open_reader_err = zip_result_reader
+                .await
+                .unwrap_or(Some(ZipReaderError::Stopped));
+            if let Some(err) = open_reader_err {
+                Err(err)
+            } else {
+                let stream: LinesStream = Box::pin(ReceiverStream::new(receiver));
+                Ok(stream)
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
+/// Entry the stream reader could not open, the entries before it were read.
+struct RejectedEntry {
+    index: usize,
+    err: ZipError,
+}
+
+/// Reads `source` with the stream reader. Fails when an entry can not be
+/// opened, e.g. one with ZIP64 sizes, later errors end up in the stream.
+async fn stream_zip_entries(
+    source: &mut (impl AsyncRead + Unpin),
+    entries: Option<&'static str>,
+    writer: &Sender<Result<String, io::Error>>,
+    zip_result_writer: &mut Option<ZipResultWriter>,
+    sample_every_line: usize,
+) -> Result<(), RejectedEntry> {
+    let mut zip = ZipFileReader::new(&mut *source);
+    for index in 0.. {
+        let mut entry = match zip.entry_reader().await {
+            // the stream reader trusts the 32-bit sizes of the local header
+            Ok(Some(entry))
+                if entry.entry().compressed_size() == Some(u32::MAX)
+                    || entry.entry().uncompressed_size() == Some(u32::MAX) =>
+            {
+                let err = ZipError::FeatureNotSupported("ZIP64 sizes");
+                return Err(RejectedEntry { index, err });
+            }
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(err) => return Err(RejectedEntry { index, err }),
+        };
+        let wanted = !entry.entry().dir()
+            && entries.is_none_or(|pattern| matches_pattern(pattern, entry.entry().name()));
+        if wanted {
+            if let Some(result_writer) = zip_result_writer.take() {
+                let _ = result_writer.send(None);
+            }
+            if !write_essa(writer, &mut entry, sample_every_line).await {
+                return Ok(());
             }
+        } else if let Err(err) = io::copy(&mut entry, &mut io::sink()).await {
+            let _ = writer.send(Err(err)).await;
+            return Ok(());
+        }
+        // entries without sizes end at a data descriptor, which has to be
+        // read, and checked against the data, before the next entry
+        if entry.entry().data_descriptor() {
+            if let Err(err) = entry.copy_to_end_crc(&mut io::sink(), 1024).await {
+                send_zip_error(writer, zip_result_writer, ZipReaderError::Unzip(err)).await;
+                return Ok(());
+            }
+        }
+    }
+    drop(zip);
+    match zip_result_writer.take() {
+        Some(result_writer) => {
+            let _ = result_writer.send(Some(no_entry(entries)));
+        }
+        None => drain(writer, source).await,
+    }
+    Ok(())
+}
+
+/// Reports `err` as the result of opening the zip if no entry was opened yet,
+/// otherwise as the last line. Spooling errors stay I/O errors, so checksum
+/// mismatches of the source are still told apart.
+async fn send_zip_error(
+    writer: &Sender<Result<String, io::Error>>,
+    zip_result_writer: &mut Option<ZipResultWriter>,
+    err: ZipReaderError,
+) {
+    match zip_result_writer.take() {
+        Some(result_writer) => {
+            let _ = result_writer.send(Some(err));
+        }
+        None => {
+            let err = match err {
+                ZipReaderError::Spooled(err) => err,
+                other => io::Error::other(other),
+            };
+            let _ = writer.send(Err(err)).await;
+        }
+    }
+}
+
+/// Spools `source`, the rest of a zip from `offset` on, to a temp file and
+/// reads the entries listed in its central directory, except the first
+/// `skip` ones the stream reader already read.
+async fn spooled_zip_entries(
+    source: &mut (impl AsyncRead + Unpin),
+    offset: u64,
+    skip: usize,
+    entries: Option<&'static str>,
+    writer: &Sender<Result<String, io::Error>>,
+    zip_result_writer: &mut Option<ZipResultWriter>,
+    sample_every_line: usize,
+) {
+    let mut zip = match SeekableZip::spool(offset, source).await {
+        Ok(zip) => zip,
+        Err(err) => {
+            send_zip_error(writer, zip_result_writer, ZipReaderError::Spooled(err)).await;
+            return;
+        }
+    };
+    let wanted = zip
+        .entries()
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .filter(|(_, entry)| {
+            !entry.dir() && entries.is_none_or(|pattern| matches_pattern(pattern, &entry.name))
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if let Some(result_writer) = zip_result_writer.take() {
+        if wanted.is_empty() {
+            let _ = result_writer.send(Some(no_entry(entries)));
+            return;
+        }
+        let _ = result_writer.send(None);
+    }
+    for index in wanted {
+        let written = match zip.entry_reader(index).await {
+            Ok(mut entry) => write_essa(writer, &mut entry, sample_every_line).await,
+            Err(err) => {
+                let _ = writer.send(Err(err)).await;
+                false
+            }
+        };
+        if !written {
+            return;
+        }
+    }
+}
+
+/// Bytes of the zip kept by [`Rewindable`], an entry the stream reader
+/// rejects has its local header within the last ones it read.
+const REWIND_LEN: usize = 1 << 20;
+
+/// Source of the stream reader keeping the last [`REWIND_LEN`] bytes read
+/// from it, so the zip can still be spooled from a rejected entry on.
+struct Rewindable<R> {
+    source: R,
+    recent: VecDeque<u8>,
+    /// Offset of the first byte of `recent` in the zip.
+    offset: u64,
+}
+
+impl<R> Rewindable<R> {
+    fn new(source: R) -> Self {
+        Self {
+            source,
+            recent: VecDeque::new(),
+            offset: 0,
+        }
+    }
+
+    /// Offset and bytes kept, followed by the unread rest of the source.
+    fn into_parts(self) -> (u64, Vec<u8>, R) {
+        (self.offset, self.recent.into(), self.source)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Rewindable<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.source).poll_read(cx, buf))?;
+        this.recent.extend(&buf.filled()[filled..]);
+        let excess = this.recent.len().saturating_sub(REWIND_LEN);
+        this.recent.drain(..excess);
+        this.offset += excess as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn no_entry(entries: Option<&'static str>) -> ZipReaderError {
+    match entries {
+        Some(pattern) => ZipReaderError::NoMatchingEntry(pattern),
+        None => ZipReaderError::EmptyZip,
+    }
+}
+
+/// Whether `name` matches `pattern`, in which `*` stands for any text.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => match name.strip_prefix(prefix) {
+            Some(name) => (0..=name.len())
+                .filter(|index| name.is_char_boundary(*index))
+                .any(|index| matches_pattern(rest, &name[index..])),
+            None => false,
+        },
+    }
+}
+
+/// Lines of a gzip compressed file, e.g. `.csv.gz`.
+pub struct GzipLinesReader<R: AsyncBufRead + Unpin> {
+    gzip: GzipDecoder<R>,
+}
+
+impl<R: AsyncBufRead + Unpin> GzipLinesReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            gzip: GzipDecoder::new(reader),
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send + 'static> LinesReader for GzipLinesReader<R> {
+    fn stream(self: Box<Self>, sample_every_line: usize) -> LinesStreamFuture {
+        let mut gzip = self.gzip;
+        let (writer, receiver) = mpsc::channel(128);
+        tokio::spawn(async move {
+            write_essa(&writer, &mut gzip, sample_every_line).await;
+            drain(&writer, gzip.get_mut()).await;
         });
-        let open_reader_err = zip_result_reader
-            .await
-            .expect("Channel should not be closed");
-        if let Some(err) = open_reader_err {
-            Err(err)
-        } else {
-            let stream = ReceiverStream::new(receiver);
-            Ok(Box::pin(stream))
-        }
-    }
-
-    async fn write_essa(
-        writer: Sender<Result<String, io::Error>>,
-        zip: &mut ZipEntryReader<'_, R>,
-        sample_every_line: usize,
-    ) {
-        let mut entry_reader = BufReader::new(zip);
-        let mut buffer = String::with_capacity(64);
-        let mut index = 0usize;
-        loop {
-            buffer.clear();
-
-            match entry_reader.read_line(&mut buffer).await {
-                Ok(_) => {
-                    if buffer.is_empty() {
-                        break;
-                    }
-                    if index % sample_every_line == 0
-                        && (writer.send(Ok(buffer.clone())).await).is_err()
-                    {
-                        break;
-                    }
-                    index += 1;
+        let stream: LinesStream = Box::pin(ReceiverStream::new(receiver));
+        Box::pin(future::ready(Ok(stream)))
+    }
+}
+
+/// Sends lines of `reader` until it ends, which is reported with `true`, or
+/// fails.
+async fn write_essa(
+    writer: &Sender<Result<String, io::Error>>,
+    reader: &mut (impl AsyncRead + Unpin),
+    sample_every_line: usize,
+) -> bool {
+    let mut entry_reader = BufReader::new(reader);
+    let mut buffer = String::with_capacity(64);
+    let mut index = 0usize;
+    loop {
+        buffer.clear();
+
+        match entry_reader.read_line(&mut buffer).await {
+            Ok(_) => {
+                if buffer.is_empty() {
+                    return true;
                 }
-                Err(err) => {
-                    if index == 0 && err.kind() == io::ErrorKind::InvalidData {
-                        continue;
-                    }
-                    let _ = writer.send(Err(err)).await;
-                    break;
+                if index.is_multiple_of(sample_every_line)
+                    && (writer.send(Ok(buffer.clone())).await).is_err()
+                {
+                    return false;
                 }
+                index += 1;
             }
+            Err(err) => {
+                if index == 0 && err.kind() == io::ErrorKind::InvalidData {
+                    continue;
+                }
+                let _ = writer.send(Err(err)).await;
+                return false;
+            }
+        }
+    }
+}
+
+/// Reads whatever the lines reader left in `source`, reporting read errors
+/// (e.g. a checksum mismatch) unless nobody listens anymore.
+async fn drain(writer: &Sender<Result<String, io::Error>>, source: &mut (impl AsyncRead + Unpin)) {
+    if writer.is_closed() {
+        return;
+    }
+    if let Err(err) = io::copy(source, &mut io::sink()).await {
+        let _ = writer.send(Err(err)).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Gzip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveOptions {
+    pub format: ArchiveFormat,
+    /// Compare every archive with the SHA-256 from its `.CHECKSUM` sibling.
+    pub verify_checksum: bool,
+    /// Name pattern of the zip entries to read, e.g. `*.csv`. Every entry is
+    /// read when unset.
+    pub entries: Option<&'static str>,
+}
+
+impl ArchiveOptions {
+    pub fn verify_checksum(mut self) -> Self {
+        self.verify_checksum = true;
+        self
+    }
+}
+
+impl From<ArchiveFormat> for ArchiveOptions {
+    fn from(format: ArchiveFormat) -> Self {
+        Self {
+            format,
+            verify_checksum: false,
+            entries: None,
+        }
+    }
+}
+
+/// Settings of the [`ArchiveClient`] shared by all workers.
+#[derive(Clone, Default)]
+pub struct ArchiveConfig {
+    pub cache: Option<Arc<ArchiveCache>>,
+    pub mirror: Option<LocalMirror>,
+    pub upstreams: Arc<Upstreams>,
+    pub proxy: Option<Proxy>,
+}
+
+impl ArchiveConfig {
+    /// Client with an HTTP thread per core.
+    pub fn client(&self) -> ArchiveClient {
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        let http = HttpPool::spawn(self.proxy.clone(), threads);
+        let mut client = ArchiveClient::new(http).with_upstreams(self.upstreams.clone());
+        if let Some(cache) = &self.cache {
+            client = client.with_cache(cache.clone());
+        }
+        if let Some(mirror) = &self.mirror {
+            client = client.with_mirror(mirror.clone());
         }
+        client
     }
 }
 
+/// HTTP client for archives, optionally backed by an [`ArchiveCache`] or
+/// replaced by a [`LocalMirror`]. Requests run on its [`HttpPool`], so the
+/// client and the bodies it opens can be used from any thread.
+#[derive(Clone)]
+pub struct ArchiveClient {
+    http: HttpPool,
+    cache: Option<Arc<ArchiveCache>>,
+    mirror: Option<LocalMirror>,
+    retry: RetryPolicy,
+    upstreams: Arc<Upstreams>,
+}
+
+impl ArchiveClient {
+    pub fn new(http: HttpPool) -> Self {
+        Self {
+            http,
+            cache: None,
+            mirror: None,
+            retry: RetryPolicy::default(),
+            upstreams: Arc::default(),
+        }
+    }
+
+    pub fn with_cache(mut self, cache: Arc<ArchiveCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_mirror(mut self, mirror: LocalMirror) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_upstreams(mut self, upstreams: Arc<Upstreams>) -> Self {
+        self.upstreams = upstreams;
+        self
+    }
+
+    pub fn http(&self) -> &HttpPool {
+        &self.http
+    }
+
+    pub fn upstreams(&self) -> &Upstreams {
+        &self.upstreams
+    }
+}
+
+pub async fn http_lines_reader(
+    client: &ArchiveClient,
+    options: ArchiveOptions,
+    url: &str,
+) -> Result<Box<dyn LinesReader>, HttpZipReaderError> {
+    let body = archive_body(client, options.verify_checksum, url).await?;
+    Ok(match options.format {
+        ArchiveFormat::Zip => Box::new(ZipLinesReader {
+            source: body,
+            entries: options.entries,
+        }),
+        ArchiveFormat::Gzip => Box::new(GzipLinesReader::new(BufReader::new(body))),
+    })
+}
+
 pub async fn http_zip_lines_reader(
-    client: &awc::Client,
+    client: &ArchiveClient,
+    url: &str,
+) -> Result<Box<dyn LinesReader>, HttpZipReaderError> {
+    http_lines_reader(client, ArchiveFormat::Zip.into(), url).await
+}
+
+/// Opens `url` from the first of its [`Upstreams`] that serves it. It is
+/// missing only when no upstream has it. Mirrored archives and `file://`
+/// urls, whether requested or upstreams, never touch the network.
+async fn archive_body(
+    client: &ArchiveClient,
+    verify_checksum: bool,
     url: &str,
-) -> Result<ZipLinesReader<impl AsyncRead + Unpin>, HttpZipReaderError> {
-    let response = client
-        .get(url)
-        .send()
+) -> Result<Box<dyn AsyncRead + Unpin + Send>, HttpZipReaderError> {
+    if let Some(mirror) = client.mirror.as_ref().filter(|_| !is_file_url(url)) {
+        let path = mirror.path(url).ok_or(HttpZipReaderError::NotFound)?;
+        return open_local_archive(&path, url, verify_checksum).await;
+    }
+    let sources = client.upstreams.urls(url);
+    let mut failure = HttpZipReaderError::NotFound;
+    for source in &sources {
+        let body = match is_file_url(source) {
+            true => match file_url_path(source) {
+                Some(path) => open_local_archive(&path, url, verify_checksum).await,
+                None => Err(HttpZipReaderError::NotFound),
+            },
+            false => upstream_body(client, verify_checksum, url, source).await,
+        };
+        match body {
+            Ok(body) => return Ok(body),
+            Err(err) => {
+                if sources.len() > 1 {
+                    warn!("Could not open {}, trying next upstream: {}", source, err);
+                }
+                if !matches!(err, HttpZipReaderError::NotFound) {
+                    failure = err;
+                }
+            }
+        }
+    }
+    Err(failure)
+}
+
+fn is_file_url(url: &str) -> bool {
+    url.starts_with("file://")
+}
+
+/// Serves `url` from the cache when `source` confirms the cached ETag is
+/// still current, otherwise downloads it, caching it along the way. Only
+/// downloads are verified, cached archives were verified when stored.
+async fn upstream_body(
+    client: &ArchiveClient,
+    verify_checksum: bool,
+    url: &str,
+    source: &str,
+) -> Result<Box<dyn AsyncRead + Unpin + Send>, HttpZipReaderError> {
+    let cached_etag = client.cache.as_ref().and_then(|cache| cache.etag(url));
+    let (body, etag) = match http_body_reader(client, source, cached_etag.as_deref()).await? {
+        HttpBody::Modified { body, etag } => (body, etag),
+        HttpBody::NotModified => {
+            let cache = client
+                .cache
+                .as_ref()
+                .expect("Only cached archives are revalidated");
+            match cache.open_cached(url).await {
+                Ok(Some(file)) => return Ok(Box::new(file)),
+                Ok(None) => debug!("{} evicted while revalidating", url),
+                Err(err) => warn!("Could not open cached {}: {}", url, err),
+            }
+            match http_body_reader(client, source, None).await? {
+                HttpBody::Modified { body, etag } => (body, etag),
+                HttpBody::NotModified => {
+                    return Err(HttpZipReaderError::InvalidStatusCode(
+                        StatusCode::NOT_MODIFIED,
+                    ))
+                }
+            }
+        }
+    };
+    let checksum = match verify_checksum {
+        true => {
+            let source = source.to_string();
+            client
+                .http
+                .run(move |http| Box::pin(async move { fetch_checksum(&http, &source).await }))
+                .await
+                .map_err(|err| HttpZipReaderError::SendRequest(err.to_string()))??
+        }
+        false => None,
+    };
+    let body: Box<dyn AsyncRead + Unpin + Send> = match checksum {
+        Some(expected) => Box::new(ChecksumReader::new(body, source, expected)),
+        None => Box::new(body),
+    };
+    Ok(match (&client.cache, etag) {
+        (Some(cache), Some(etag)) => Box::new(cache.tee(body, url, etag).await),
+        _ => body,
+    })
+}
+
+enum HttpBody<R> {
+    Modified { body: R, etag: Option<String> },
+    NotModified,
+}
+
+/// Failed requests are retried and interrupted bodies resumed as configured
+/// by the client's [`RetryPolicy`].
+async fn http_body_reader(
+    client: &ArchiveClient,
+    url: &str,
+    if_none_match: Option<&str>,
+) -> Result<HttpBody<impl AsyncBufRead + Unpin + Send>, HttpZipReaderError> {
+    let retry = client.retry;
+    let url = url.to_string();
+    let if_none_match = if_none_match.map(str::to_string);
+    client
+        .http
+        .run(move |http| {
+            Box::pin(async move {
+                let response = send_retrying(retry, &url, || {
+                    let request = http.get(&url).no_decompress();
+                    match &if_none_match {
+                        Some(etag) => request.insert_header((header::IF_NONE_MATCH, etag.as_str())),
+                        None => request,
+                    }
+                })
+                .await
+                .map_err(|err| HttpZipReaderError::SendRequest(err.to_string()))?;
+                let code = response.status();
+                if code == StatusCode::NOT_MODIFIED && if_none_match.is_some() {
+                    Ok(HttpBody::NotModified)
+                } else if code.is_success() {
+                    let etag = response
+                        .headers()
+                        .get(header::ETAG)
+                        .and_then(|etag| etag.to_str().ok())
+                        .map(str::to_string);
+                    let body = resumable_body(http.clone(), retry, &url, etag.clone(), response);
+                    Ok(HttpBody::Modified {
+                        body: StreamReader::new(forward(body)),
+                        etag,
+                    })
+                } else if code == StatusCode::NOT_FOUND {
+                    Err(HttpZipReaderError::NotFound)
+                } else {
+                    Err(HttpZipReaderError::InvalidStatusCode(code))
+                }
+            })
+        })
         .await
-        .map_err(HttpZipReaderError::SendRequest)?;
-    let code = response.status();
-    if code.is_success() {
-        let zip = ZipFileReader::new(StreamReader::new(
-            response.map_err(|err| io::Error::new(io::ErrorKind::Other, err)),
-        ));
-        Ok(ZipLinesReader { zip })
-    } else if code == StatusCode::NOT_FOUND {
-        Err(HttpZipReaderError::NotFound)
-    } else {
-        Err(HttpZipReaderError::InvalidStatusCode(code))
+        .map_err(|err| HttpZipReaderError::SendRequest(err.to_string()))?
+}
+
+/// Archive read by a [`CsvRangeTradeReader`], with the archives holding the
+/// same rows to read instead while it is not published, e.g. the days of a
+/// monthly archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveUrl {
+    pub url: String,
+    pub fallback: Vec<String>,
+}
+
+impl From<String> for ArchiveUrl {
+    fn from(url: String) -> Self {
+        Self {
+            url,
+            fallback: Vec::new(),
+        }
     }
 }
 
+pub async fn http_csv_range_reader<T>(
+    client: &ArchiveClient,
+    options: ArchiveOptions,
+    urls: Vec<impl Into<ArchiveUrl>>,
+    parse_trade: TradeParser<T>,
+) -> Result<CsvRangeTradeReader<T>, HttpZipReaderError> {
+    let mut urls = urls.into_iter().map(Into::into);
+    let first_url = urls.next().ok_or(HttpZipReaderError::NotFound)?;
+    let (lines_reader, rest_urls) = match http_lines_reader(client, options, &first_url.url).await {
+        Err(HttpZipReaderError::NotFound) if !first_url.fallback.is_empty() => {
+            debug!("{} is not published, reading its fallback", first_url.url);
+            let mut fallback = first_url.fallback.into_iter();
+            let lines_reader =
+                http_lines_reader(client, options, &fallback.next().unwrap()).await?;
+            (
+                lines_reader,
+                fallback.map(ArchiveUrl::from).chain(urls).collect(),
+            )
+        }
+        lines_reader => (lines_reader?, urls.collect()),
+    };
+    Ok(CsvRangeTradeReader {
+        client: client.clone(),
+        options,
+        first: CsvTradeReader::new(lines_reader, parse_trade),
+        rest_urls,
+        window: None,
+    })
+}
+
 #[derive(Debug, Error)]
 pub enum TradeReaderError {
     #[error("Could not read zip: {0}")]
@@ -252,18 +1386,31 @@ pub enum TradeReaderError {
     IO(io::Error),
     #[error("Could not parse trade: {0}")]
     Parse(ParseTradeError),
+    #[error("Could not open {0}: {1}")]
+    HttpZipReader(String, HttpZipReaderError),
+    /// Failure of a page of an API without archives.
+    #[error("Could not read trades page: {0}")]
+    Page(Box<dyn std::error::Error + Send + Sync>),
 }
 
 #[derive(Debug, Error)]
 pub enum HttpZipReaderError {
     #[error("Could not send request: {0}")]
-    SendRequest(SendRequestError),
+    SendRequest(String),
     #[error("Zip reader error: {0}")]
     ZipReader(ZipReaderError),
     #[error("Trades for given params are not present.")]
     NotFound,
     #[error("Server returned invalid status code: {0}")]
     InvalidStatusCode(StatusCode),
+    #[error("Could not read checksum: {0}")]
+    ReadChecksum(PayloadError),
+    #[error("Invalid checksum file: {0:?}")]
+    InvalidChecksumFile(String),
+    #[error("Archive is corrupt: {0}")]
+    ChecksumMismatch(ChecksumMismatch),
+    #[error("Could not read local archive: {0}")]
+    ReadFile(io::Error),
 }
 
 #[derive(Debug, Error)]
@@ -272,6 +1419,12 @@ pub enum ZipReaderError {
     Unzip(ZipError),
     #[error("Empty zip")]
     EmptyZip,
+    #[error("No zip entry matches {0}")]
+    NoMatchingEntry(&'static str),
+    #[error("Could not read spooled zip: {0}")]
+    Spooled(io::Error),
+    #[error("Zip reader stopped before opening an entry")]
+    Stopped,
 }
 
 #[derive(Debug, Error)]
@@ -287,13 +1440,700 @@ pub enum ParseTradeError {
     #[error("Missing price")]
     MissingPrice,
     #[error("Invalid price: {0}")]
-    InvalidPrice(ParseFloatError),
+    InvalidPrice(rust_decimal::Error),
+    #[error("Price is zero")]
+    ZeroPrice,
+    #[error("Missing quantity")]
+    MissingQuantity,
+    #[error("Invalid quantity: {0}")]
+    InvalidQuantity(rust_decimal::Error),
     #[error("Missing timestamp")]
     MissingTimestamp,
     #[error("Invalid timestamp: {0}")]
     InvalidTimestamp(ParseIntError),
+    #[error("Timestamp out of range: {0}")]
+    TimestampOutOfRange(String),
+    #[error("Missing column {0} in header")]
+    MissingColumn(&'static str),
+    #[error("Missing candle {0}")]
+    MissingCandleField(&'static str),
+    #[error("Invalid candle {0}: {1}")]
+    InvalidCandleField(&'static str, rust_decimal::Error),
     #[error("Underlying stream error: {0}")]
     Stream(io::Error),
     #[error("IO error: {0}")]
     IO(io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::seekable_zip::tests::stored_zip;
+    use crate::exchange::test_server::serve;
+    use actix_web::{web, HttpResponse};
+    use async_zip::write::{EntryOptions, ZipFileWriter};
+    use async_zip::Compression;
+    use sha2::{Digest, Sha256};
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const FIXTURE: &str =
+        "id,side,price,timestamp\n1,buy,20180.5,1663027200557\n2,sell,20181,1663027201100\n";
+
+    async fn zip(text: &str) -> Vec<u8> {
+        zip_entries(&[("trades.csv", text)]).await
+    }
+
+    async fn zip_entries(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut writer = ZipFileWriter::new(&mut archive);
+        for (name, text) in entries {
+            writer
+                .write_entry_whole(
+                    EntryOptions::new(name.to_string(), Compression::Deflate),
+                    text.as_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+        writer.close().await.unwrap();
+        archive
+    }
+
+    fn parse_row(row: String) -> Result<Trade, ParseTradeError> {
+        let columns = row.trim_end().split(',').collect::<Vec<_>>();
+        Ok(Trade {
+            id: columns[0].parse().unwrap(),
+            side: TradeSide::Buy,
+            price: columns[2].parse().unwrap(),
+            quantity: Decimal::ONE,
+            quote_quantity: columns[2].parse().unwrap(),
+            timestamp: Timestamp::from_millis(columns[3].parse().unwrap()),
+        })
+    }
+
+    const ROW_PARSER: TradeParser =
+        TradeParser::new(&["id", "side", "price", "timestamp"], parse_row);
+
+    /// Lines of `text` as if read from an archive.
+    struct TextLines(&'static str);
+
+    impl LinesReader for TextLines {
+        fn stream(self: Box<Self>, _sample_every_line: usize) -> LinesStreamFuture {
+            let lines = self
+                .0
+                .split_inclusive('\n')
+                .map(|line| Ok(line.to_string()))
+                .collect::<Vec<_>>();
+            let stream: LinesStream = Box::pin(futures::stream::iter(lines));
+            Box::pin(future::ready(Ok(stream)))
+        }
+    }
+
+    async fn read_rows(text: &'static str) -> Vec<ReadResult> {
+        CsvTradeReader::new(Box::new(TextLines(text)), ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .collect()
+            .await
+    }
+
+    /// Serves `/ok.zip` with a matching checksum and `/corrupt.zip` with one
+    /// of a different archive.
+    async fn checksum_server() -> String {
+        let archive = zip(FIXTURE).await;
+        let checksum = hex::encode(Sha256::digest(&archive));
+        let other_checksum = hex::encode(Sha256::digest(b"other"));
+        serve(move |config| {
+            let archive = archive.clone();
+            let checksum = format!("{}  ok.zip\n", checksum);
+            let other_checksum = format!("{}  corrupt.zip\n", other_checksum);
+            config
+                .route(
+                    "/{name}.zip",
+                    web::get().to(move || {
+                        let archive = archive.clone();
+                        async move { HttpResponse::Ok().body(archive) }
+                    }),
+                )
+                .route(
+                    "/ok.zip.CHECKSUM",
+                    web::get().to(move || {
+                        let checksum = checksum.clone();
+                        async move { HttpResponse::Ok().body(checksum) }
+                    }),
+                )
+                .route(
+                    "/corrupt.zip.CHECKSUM",
+                    web::get().to(move || {
+                        let other_checksum = other_checksum.clone();
+                        async move { HttpResponse::Ok().body(other_checksum) }
+                    }),
+                );
+        })
+    }
+
+    async fn read_verified(url: &str) -> Vec<ReadResult> {
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let options = ArchiveOptions::from(ArchiveFormat::Zip).verify_checksum();
+        let lines_reader = http_lines_reader(&client, options, url).await.unwrap();
+        CsvTradeReader::new(lines_reader, ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .collect()
+            .await
+    }
+
+    #[actix_web::test]
+    async fn verify_archive_checksum() {
+        let base_url = checksum_server().await;
+
+        let trades = read_verified(&format!("{}/ok.zip", base_url)).await;
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(Result::is_ok));
+
+        let url = format!("{}/corrupt.zip", base_url);
+        let mut trades = read_verified(&url).await;
+        assert_eq!(trades.len(), 3);
+        match trades.pop() {
+            Some(Err(TradeReaderError::HttpZipReader(
+                failed_url,
+                HttpZipReaderError::ChecksumMismatch(mismatch),
+            ))) => {
+                assert_eq!(failed_url, url);
+                assert_eq!(mismatch.expected, hex::encode(Sha256::digest(b"other")));
+            }
+            other => panic!("Expected checksum mismatch, got {:?}", other),
+        }
+
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let options = ArchiveOptions::from(ArchiveFormat::Zip).verify_checksum();
+        assert!(matches!(
+            http_lines_reader(&client, options, &format!("{}/missing", base_url)).await,
+            Err(HttpZipReaderError::NotFound)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn fail_over_to_next_upstream() {
+        let base_url = checksum_server().await;
+        let upstreams = Upstreams::default().with_mirrors(
+            "https://data.example.com",
+            vec![
+                "http://127.0.0.1:1".to_string(),
+                format!("{}/missing-mirror", base_url),
+                base_url.clone(),
+            ],
+        );
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1))
+            .with_retry(RetryPolicy {
+                retries: 0,
+                backoff: std::time::Duration::ZERO,
+            })
+            .with_upstreams(Arc::new(upstreams));
+
+        let reader = http_lines_reader(
+            &client,
+            ArchiveFormat::Zip.into(),
+            "https://data.example.com/ok.zip",
+        )
+        .await
+        .unwrap();
+        let trades: Vec<ReadResult> = CsvTradeReader::new(reader, ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(trades.len(), 2);
+        assert!(matches!(
+            http_zip_lines_reader(&client, "https://data.example.com/missing").await,
+            Err(HttpZipReaderError::SendRequest(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn revalidate_cached_archive() {
+        let archive = zip(FIXTURE).await;
+        let downloads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let server_downloads = downloads.clone();
+        let base_url = serve(move |config| {
+            let archive = archive.clone();
+            let downloads = server_downloads.clone();
+            config.route(
+                "/cached.zip",
+                web::get().to(move |request: actix_web::HttpRequest| {
+                    let archive = archive.clone();
+                    let downloads = downloads.clone();
+                    async move {
+                        let etag = "\"v1\"";
+                        if request.headers().get(header::IF_NONE_MATCH)
+                            == Some(&header::HeaderValue::from_static(etag))
+                        {
+                            return HttpResponse::NotModified().finish();
+                        }
+                        downloads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        HttpResponse::Ok()
+                            .insert_header((header::ETAG, etag))
+                            .body(archive)
+                    }
+                }),
+            );
+        });
+        let url = format!("{}/cached.zip", base_url);
+
+        let dir = std::env::temp_dir().join(format!("revalidate-{}", std::process::id()));
+        let cache = Arc::new(ArchiveCache::open(&dir, 1024 * 1024).unwrap());
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1)).with_cache(cache.clone());
+        for _ in 0..2 {
+            let lines_reader = http_zip_lines_reader(&client, &url).await.unwrap();
+            let trades: Vec<ReadResult> = CsvTradeReader::new(lines_reader, ROW_PARSER)
+                .stream(1)
+                .await
+                .unwrap()
+                .collect()
+                .await;
+            assert_eq!(trades.len(), 2);
+            assert!(trades.iter().all(Result::is_ok));
+        }
+        assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(cache.etag(&url), Some("\"v1\"".to_string()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn read_local_archives() {
+        let dir = std::env::temp_dir().join(format!("local-mirror-{}", std::process::id()));
+        let mirrored = dir.join("example.com/daily/BTCUSDT-trades-2022-09-13.zip");
+        std::fs::create_dir_all(mirrored.parent().unwrap()).unwrap();
+        std::fs::write(&mirrored, zip(FIXTURE).await).unwrap();
+        let client =
+            ArchiveClient::new(HttpPool::spawn(None, 1)).with_mirror(LocalMirror::new(&dir));
+
+        for url in [
+            "https://example.com/daily/BTCUSDT-trades-2022-09-13.zip".to_string(),
+            format!("file://{}", mirrored.display()),
+        ] {
+            let lines_reader = http_zip_lines_reader(&client, &url).await.unwrap();
+            let trades: Vec<ReadResult> = CsvTradeReader::new(lines_reader, ROW_PARSER)
+                .stream(1)
+                .await
+                .unwrap()
+                .collect()
+                .await;
+            assert_eq!(trades.len(), 2);
+        }
+        assert!(matches!(
+            http_zip_lines_reader(&client, "https://example.com/daily/missing.zip").await,
+            Err(HttpZipReaderError::NotFound)
+        ));
+
+        let upstreams = Upstreams::default().with_mirrors(
+            "https://data.example.com",
+            vec![
+                format!("file://{}/missing", dir.display()),
+                format!("file://{}", dir.join("example.com").display()),
+            ],
+        );
+        let client =
+            ArchiveClient::new(HttpPool::spawn(None, 1)).with_upstreams(Arc::new(upstreams));
+        let lines_reader = http_zip_lines_reader(
+            &client,
+            "https://data.example.com/daily/BTCUSDT-trades-2022-09-13.zip",
+        )
+        .await
+        .unwrap();
+        let trades: Vec<ReadResult> = CsvTradeReader::new(lines_reader, ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(trades.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn read_fallback_of_missing_archives() {
+        let dir = std::env::temp_dir().join(format!("fallback-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = |name: &str| format!("file://{}", dir.join(name).display());
+        for name in ["day-1.zip", "day-2.zip"] {
+            std::fs::write(dir.join(name), zip(FIXTURE).await).unwrap();
+        }
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1));
+        let urls = vec![
+            ArchiveUrl {
+                url: url("month-1.zip"),
+                fallback: vec![url("day-1.zip")],
+            },
+            ArchiveUrl {
+                url: url("month-2.zip"),
+                fallback: vec![url("day-2.zip")],
+            },
+        ];
+
+        let trades: Vec<ReadResult> =
+            http_csv_range_reader(&client, ArchiveFormat::Zip.into(), urls, ROW_PARSER)
+                .await
+                .unwrap()
+                .stream(1)
+                .await
+                .unwrap()
+                .collect()
+                .await;
+        assert_eq!(trades.len(), 4);
+        assert!(trades.iter().all(Result::is_ok));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Serves `archive` dropping the first two connections after a third of
+    /// the body, the whole archive again instead of the range asked for unless
+    /// `ranges`. Returns the url and `Range` starts of the requests.
+    async fn flaky_server(
+        archive: Vec<u8>,
+        ranges: bool,
+    ) -> (String, Arc<Mutex<Vec<Option<usize>>>>) {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let url = format!("http://{}/flaky.zip", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0u8];
+                    socket.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap().to_lowercase();
+                let range_start = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .map(|range| range.trim_end_matches('-').parse::<usize>().unwrap());
+                let attempt = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(range_start);
+                    seen.len()
+                };
+                let range_start = range_start.filter(|_| ranges);
+                let rest = &archive[range_start.unwrap_or(0)..];
+                let head = match range_start {
+                    None => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\netag: \"v1\"\r\n\r\n",
+                        rest.len()
+                    ),
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\ncontent-length: {}\r\ncontent-range: bytes {}-{}/{}\r\n\r\n",
+                        rest.len(),
+                        start,
+                        archive.len() - 1,
+                        archive.len()
+                    ),
+                };
+                let sent = match attempt {
+                    1 | 2 => rest.len() / 3,
+                    _ => rest.len(),
+                };
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&rest[..sent]).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[actix_web::test]
+    async fn resume_interrupted_download() {
+        let rows = (1..=5000)
+            .map(|id| format!("{},buy,20180.5,{}\n", id, 1663027200000u64 + id))
+            .collect::<String>();
+        let (url, requests) =
+            flaky_server(zip(&format!("id,side,price,timestamp\n{rows}")).await, true).await;
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1)).with_retry(RetryPolicy {
+            retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+        });
+
+        let lines_reader = http_zip_lines_reader(&client, &url).await.unwrap();
+        let ids = CsvTradeReader::new(lines_reader, ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .map(|trade| trade.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, (1..=5000).collect::<Vec<_>>());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0], None);
+        assert!(matches!(
+            (requests[1], requests[2]),
+            (Some(first), Some(second)) if 0 < first && first < second
+        ));
+    }
+
+    #[actix_web::test]
+    async fn fail_download_resumed_elsewhere() {
+        let rows = (1..=5000)
+            .map(|id| format!("{},buy,20180.5,{}\n", id, 1663027200000u64 + id))
+            .collect::<String>();
+        let (url, requests) = flaky_server(
+            zip(&format!("id,side,price,timestamp\n{rows}")).await,
+            false,
+        )
+        .await;
+        let client = ArchiveClient::new(HttpPool::spawn(None, 1)).with_retry(RetryPolicy {
+            retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+        });
+
+        let lines_reader = http_zip_lines_reader(&client, &url).await.unwrap();
+        let trades = CsvTradeReader::new(lines_reader, ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let ids = trades
+            .iter()
+            .map_while(|trade| trade.as_ref().ok().map(|trade| trade.id))
+            .collect::<Vec<_>>();
+        assert!(trades.last().unwrap().is_err());
+        assert_eq!(ids, (1..=ids.len() as u64).collect::<Vec<_>>());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn read_archives_on_thread_pool() {
+        let rows = (1..=5000)
+            .map(|id| format!("{},buy,20180.5,{}\n", id, 1663027200000u64 + id))
+            .collect::<String>();
+        let (url, _) =
+            flaky_server(zip(&format!("id,side,price,timestamp\n{rows}")).await, true).await;
+        let client = ArchiveClient::new(HttpPool::spawn(None, 2)).with_retry(RetryPolicy {
+            retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+        });
+
+        let read = tokio::spawn(async move {
+            http_csv_range_reader(&client, ArchiveFormat::Zip.into(), vec![url], ROW_PARSER)
+                .await
+                .unwrap()
+                .stream(1)
+                .await
+                .unwrap()
+                .map(|trade| trade.unwrap().id)
+                .collect::<Vec<_>>()
+                .await
+        });
+        assert_eq!(read.await.unwrap(), (1..=5000).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn read_every_zip_entry() {
+        let archive = zip_entries(&[
+            ("2022-09-13/trades.csv", FIXTURE),
+            ("README.txt", "Trades of 2022-09-13 and 2022-09-14\n"),
+            (
+                "2022-09-14/trades.csv",
+                "timestamp,price,side,id\n1663113600000,20100,sell,3\n",
+            ),
+        ])
+        .await;
+        let read = |entries| {
+            let lines_reader = ZipLinesReader {
+                source: std::io::Cursor::new(archive.clone()),
+                entries,
+            };
+            CsvTradeReader::new(Box::new(lines_reader), ROW_PARSER).stream(1)
+        };
+
+        let ids = read(Some("*.csv"))
+            .await
+            .unwrap()
+            .map(|trade| trade.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert!(matches!(
+            read(Some("*.parquet")).await,
+            Err(TradeReaderError::ZipReader(
+                ZipReaderError::NoMatchingEntry("*.parquet")
+            ))
+        ));
+        assert!(matches_pattern(
+            "2022-*/trades.csv",
+            "2022-09-14/trades.csv"
+        ));
+        assert!(!matches_pattern("*.csv", "README.txt"));
+    }
+
+    #[actix_web::test]
+    async fn spool_zip_from_rejected_entry() {
+        let archive = stored_zip(&[
+            (
+                "2022-09-13.csv",
+                "timestamp,price,side,id\n1663027200000,20000,buy,1\n",
+                false,
+            ),
+            ("README.txt", "Trades of 2022-09-13 and 2022-09-14\n", false),
+            (
+                "2022-09-14.csv",
+                "timestamp,price,side,id\n1663113600000,20100,sell,2\n",
+                true,
+            ),
+        ]);
+        let lines_reader = ZipLinesReader {
+            source: std::io::Cursor::new(archive),
+            entries: Some("*.csv"),
+        };
+
+        let ids = CsvTradeReader::new(Box::new(lines_reader), ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .map(|trade| trade.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[actix_web::test]
+    async fn read_zip_with_data_descriptors() {
+        let mut archive = Vec::new();
+        let mut writer = ZipFileWriter::new(&mut archive);
+        for text in [
+            FIXTURE,
+            "timestamp,price,side,id\n1663113600000,20100,sell,3\n",
+        ] {
+            let mut entry = writer
+                .write_entry_stream(EntryOptions::new(
+                    "trades.csv".to_string(),
+                    Compression::Deflate,
+                ))
+                .await
+                .unwrap();
+            entry.write_all(text.as_bytes()).await.unwrap();
+            entry.close().await.unwrap();
+        }
+        writer.close().await.unwrap();
+        let lines_reader = ZipLinesReader {
+            source: std::io::Cursor::new(archive.clone()),
+            entries: None,
+        };
+
+        let ids = CsvTradeReader::new(Box::new(lines_reader), ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .map(|trade| trade.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        // a wrong checksum in the first data descriptor ends the stream
+        let descriptor = archive
+            .windows(4)
+            .position(|window| window == [0x50, 0x4b, 0x07, 0x08])
+            .unwrap();
+        archive[descriptor + 4] ^= 0xff;
+        let lines_reader = ZipLinesReader {
+            source: std::io::Cursor::new(archive),
+            entries: None,
+        };
+        let trades = CsvTradeReader::new(Box::new(lines_reader), ROW_PARSER)
+            .stream(1)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(trades.len(), 3);
+        assert!(trades[2].is_err());
+    }
+
+    #[actix_web::test]
+    async fn map_columns_by_header() {
+        let trades = read_rows("1,buy,20180.5,1663027200557\n2,sell,20181,1663027201100\n").await;
+        assert_eq!(
+            trades
+                .iter()
+                .map(|trade| trade.as_ref().unwrap().id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let trades =
+            read_rows("Timestamp,Price,Venue,Side,ID\n1663027200557,20180.5,x,buy,7\n").await;
+        let trade = trades[0].as_ref().unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trade.id, 7);
+        assert_eq!(trade.price, Decimal::new(201805, 1));
+        assert_eq!(trade.timestamp, Timestamp::from_millis(1663027200557));
+
+        let trades =
+            read_rows("id,side,timestamp\n1,buy,1663027200557\n2,buy,1663027200558\n").await;
+        assert_eq!(trades.len(), 1);
+        assert!(matches!(
+            trades[0],
+            Err(TradeReaderError::Parse(ParseTradeError::MissingColumn(
+                "price"
+            )))
+        ));
+    }
+
+    #[test]
+    fn detect_timestamp_unit() {
+        let millis = Timestamp::from_millis(1735689600123);
+        assert_eq!(
+            Timestamp::detect(1735689600),
+            Timestamp::from_millis(1735689600000)
+        );
+        assert_eq!(Timestamp::detect(1735689600123), millis);
+        assert_eq!(
+            Timestamp::detect(1735689600123456).as_millis(),
+            1735689600123
+        );
+        assert_eq!(
+            Timestamp::detect(1735689600123456789).as_micros(),
+            1735689600123456
+        );
+        assert_eq!(
+            TimestampUnit::detect(1735689600123456),
+            TimestampUnit::Micros
+        );
+        assert!(Timestamp::detect(1735689600123456) > millis);
+    }
+
+    #[test]
+    fn parse_trade_pair() {
+        assert_eq!("BTC-USDT".parse(), Ok(TradePair::new("BTC", "USDT")));
+        assert_eq!(
+            "BTC-USD-PERP".parse(),
+            Ok(TradePair::new("BTC", "USD").with_contract(Contract::Perpetual))
+        );
+        assert_eq!(
+            "BTC-USD_221230".parse(),
+            Ok(TradePair::new("BTC", "USD")
+                .with_contract(Contract::Delivery(NaiveDate::from_ymd(2022, 12, 30))))
+        );
+        assert_eq!(
+            "BTC-USDT-SWAP".parse(),
+            Ok(TradePair::new("BTC", "USDT").with_contract(Contract::Perpetual))
+        );
+        assert_eq!("BTC".parse::<TradePair>(), Err(()));
+        assert_eq!("BTC-USD-NEXT".parse::<TradePair>(), Err(()));
+        assert_eq!("BTC-USD-PERP-X".parse::<TradePair>(), Err(()));
+    }
+
+    #[test]
+    fn display_trade_pair() {
+        let pair = TradePair::new("BTC", "USD")
+            .with_contract(Contract::Delivery(NaiveDate::from_ymd(2022, 12, 30)));
+        assert_eq!(pair.to_string(), "BTC-USD-221230");
+    }
+}
@@ -0,0 +1,74 @@
+/// Base urls tried in order instead of an exchange's own one, e.g. an internal
+/// mirror followed by the exchange CDN. Urls keep their path, so mirrors have
+/// to use the upstream layout.
+#[derive(Debug, Clone, Default)]
+pub struct Upstreams {
+    mirrors: Vec<(String, Vec<String>)>,
+}
+
+impl Upstreams {
+    /// Replaces `base_url` with `mirrors`, it is not tried unless listed.
+    /// Without any mirrors `base_url` stays as is.
+    pub fn with_mirrors(mut self, base_url: &str, mirrors: Vec<String>) -> Self {
+        if mirrors.is_empty() {
+            return self;
+        }
+        let mirrors = mirrors
+            .iter()
+            .map(|mirror| mirror.trim_end_matches('/').to_string())
+            .collect();
+        self.mirrors
+            .push((base_url.trim_end_matches('/').to_string(), mirrors));
+        self
+    }
+
+    /// `url` on every mirror of its base url in order, or just `url` itself.
+    pub fn urls(&self, url: &str) -> Vec<String> {
+        self.mirrors
+            .iter()
+            .find_map(|(base_url, mirrors)| {
+                let path = url.strip_prefix(base_url.as_str())?;
+                match path.is_empty() || path.starts_with(['/', '?']) {
+                    true => Some(
+                        mirrors
+                            .iter()
+                            .map(|mirror| format!("{}{}", mirror, path))
+                            .collect(),
+                    ),
+                    false => None,
+                }
+            })
+            .unwrap_or_else(|| vec![url.to_string()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_urls() {
+        let upstreams = Upstreams::default().with_mirrors(
+            "https://data.binance.vision",
+            vec![
+                "http://mirror.local/binance/".to_string(),
+                "https://data.binance.vision".to_string(),
+            ],
+        );
+        assert_eq!(
+            upstreams.urls("https://data.binance.vision/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip"),
+            vec![
+                "http://mirror.local/binance/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip",
+                "https://data.binance.vision/data/spot/daily/trades/BTCUSDT/BTCUSDT-trades-2022-09-13.zip",
+            ]
+        );
+        assert_eq!(
+            upstreams.urls("https://data.binance.vision.evil.com/a.zip"),
+            vec!["https://data.binance.vision.evil.com/a.zip"]
+        );
+        assert_eq!(
+            upstreams.urls("https://static.okx.com/a.zip"),
+            vec!["https://static.okx.com/a.zip"]
+        );
+    }
+}
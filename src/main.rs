@@ -3,12 +3,20 @@ extern crate core;
 pub mod exchange;
 mod website;
 
-use crate::exchange::binance;
+use crate::exchange::archive_cache::ArchiveCache;
+use crate::exchange::local_mirror::LocalMirror;
+use crate::exchange::proxy::Proxy;
+use crate::exchange::trade_reader::ArchiveConfig;
+use crate::exchange::upstream::Upstreams;
+use crate::exchange::{binance, bybit, olx};
 use crate::website::Credentials;
 use env_logger::Env;
 use log::info;
 use std::env;
 use std::env::VarError;
+use std::sync::Arc;
+
+const DEFAULT_ARCHIVE_CACHE_MB: u64 = 10 * 1024;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -21,7 +29,92 @@ async fn main() -> std::io::Result<()> {
         Some(_) => info!("Credentials provided. To access website password is required!"),
         None => info!("Credentials NOT provided, website will be accessible WITHOUT password."),
     }
-    website::start(credentials).await
+    let archive_config = ArchiveConfig {
+        cache: open_archive_cache()?,
+        mirror: local_mirror(),
+        upstreams: Arc::new(parse_upstreams()),
+        proxy: parse_proxy(),
+    };
+    website::start(credentials, archive_config).await
+}
+
+/// Upstream traffic goes through `UPSTREAM_PROXY` when it is set, e.g.
+/// `http://proxy:3128` or `socks5://proxy:1080`.
+fn parse_proxy() -> Option<Proxy> {
+    match env::var("UPSTREAM_PROXY") {
+        Ok(proxy) => {
+            let proxy = proxy
+                .parse::<Proxy>()
+                .unwrap_or_else(|err| panic!("UPSTREAM_PROXY {proxy} is invalid: {err}"));
+            info!("Upstream connections will go through {proxy}.");
+            Some(proxy)
+        }
+        Err(VarError::NotPresent) => None,
+        Err(VarError::NotUnicode(_)) => panic!("UPSTREAM_PROXY is not valid unicode string"),
+    }
+}
+
+/// Comma separated base urls tried in order instead of the upstream one, e.g.
+/// `BINANCE_DATA_URLS=http://mirror.local/binance,https://data.binance.vision`.
+fn parse_upstreams() -> Upstreams {
+    [
+        ("BINANCE_DATA_URLS", binance::data::DATA_URL),
+        ("BINANCE_BUCKET_URLS", binance::data::BUCKET_URL),
+        ("OKX_DATA_URLS", olx::data::DATA_URL),
+        ("BYBIT_DATA_URLS", bybit::data::PUBLIC_DATA_URL),
+    ]
+    .into_iter()
+    .fold(
+        Upstreams::default(),
+        |upstreams, (key, base_url)| match env::var(key) {
+            Ok(urls) => {
+                let mirrors = urls
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                info!("Archives of {base_url} will be fetched from {mirrors:?}");
+                upstreams.with_mirrors(base_url, mirrors)
+            }
+            Err(VarError::NotPresent) => upstreams,
+            Err(VarError::NotUnicode(_)) => panic!("{key} is not valid unicode string"),
+        },
+    )
+}
+
+/// Archives are read from `LOCAL_ARCHIVE_DIR` instead of downloading them
+/// when it is set.
+fn local_mirror() -> Option<LocalMirror> {
+    match env::var("LOCAL_ARCHIVE_DIR") {
+        Ok(dir) => {
+            info!("Archives will be read from {dir} only.");
+            Some(LocalMirror::new(dir))
+        }
+        Err(VarError::NotPresent) => None,
+        Err(VarError::NotUnicode(_)) => panic!("LOCAL_ARCHIVE_DIR is not valid unicode string"),
+    }
+}
+
+/// Archives are cached on disk only when `ARCHIVE_CACHE_DIR` is set, up to
+/// `ARCHIVE_CACHE_MB` megabytes.
+fn open_archive_cache() -> std::io::Result<Option<Arc<ArchiveCache>>> {
+    let dir = match env::var("ARCHIVE_CACHE_DIR") {
+        Ok(dir) => dir,
+        Err(VarError::NotPresent) => {
+            info!("ARCHIVE_CACHE_DIR NOT provided, archives will not be cached.");
+            return Ok(None);
+        }
+        Err(VarError::NotUnicode(_)) => panic!("ARCHIVE_CACHE_DIR is not valid unicode string"),
+    };
+    let max_mb = match env::var("ARCHIVE_CACHE_MB") {
+        Ok(max_mb) => max_mb
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("ARCHIVE_CACHE_MB is not a number: {max_mb}")),
+        Err(_) => DEFAULT_ARCHIVE_CACHE_MB,
+    };
+    let cache = ArchiveCache::open(dir, max_mb * 1024 * 1024)?;
+    Ok(Some(Arc::new(cache)))
 }
 
 fn parse_credentials() -> Option<Credentials> {
@@ -1,7 +1,9 @@
-use crate::binance;
-use crate::exchange::olx;
+use crate::exchange::registry::{DatasetRequest, Exchange, ExchangeError, ExchangeRegistry};
+use crate::exchange::symbol_cache::{SymbolCache, SYMBOLS_MAX_AGE};
 use crate::exchange::trade_reader::{
-    HttpZipReaderError, Trade, TradePair, TradeReader, TradeReaderError, TradeSide,
+    ArchiveClient, ArchiveConfig, ArchivePeriod, Candle, CreateStream, DatasetType,
+    HttpZipReaderError, KlineInterval, Market, ReadResult, Trade, TradePair, TradeReaderError,
+    TradeSide,
 };
 use actix_files::Files;
 use actix_web::dev::ServiceRequest;
@@ -11,16 +13,16 @@ use actix_web::{get, middleware, web, App, HttpResponse, HttpServer, ResponseErr
 use actix_web_httpauth::extractors::basic::BasicAuth;
 use actix_web_httpauth::extractors::{basic, AuthenticationError};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate};
 use futures::{Stream, StreamExt, TryStreamExt};
 use log::{debug, error, info};
+use rust_decimal::Decimal;
 use serde_json::json;
-use std::future;
-use std::future::Ready;
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
 use tokio::io::{AsyncWriteExt, DuplexStream};
-use tokio::task::{spawn_local, JoinHandle};
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 use tokio_util::codec::BytesCodec;
 use tokio_util::codec::FramedRead;
@@ -62,9 +64,19 @@ async fn login(
     }
 }
 
-pub async fn start(server_credentials: Option<Credentials>) -> std::io::Result<()> {
+pub async fn start(
+    server_credentials: Option<Credentials>,
+    archive_config: ArchiveConfig,
+) -> std::io::Result<()> {
+    let symbol_cache = Arc::new(SymbolCache::new(SYMBOLS_MAX_AGE));
+    let http_client = archive_config.client();
+    actix_web::rt::spawn(
+        symbol_cache
+            .clone()
+            .refresh_periodically(ExchangeRegistry::default(), http_client.clone()),
+    );
     HttpServer::new(move || {
-        let http_client = awc::Client::new();
+        let exchanges = ExchangeRegistry::default();
         App::new()
             .configure(|config| {
                 if let Some(server_credentials) = server_credentials.clone() {
@@ -82,9 +94,15 @@ pub async fn start(server_credentials: Option<Credentials>) -> std::io::Result<(
                         server_credentials.is_some(),
                         HttpAuthentication::basic(login),
                     ))
+                    .service(view_exchanges)
+                    .service(view_symbols)
+                    .service(view_availability)
                     .service(view_detailed_dataset)
+                    .service(view_candles)
                     .service(Files::new("/", "./frontend").index_file("index.html"))
-                    .app_data(web::Data::new(http_client)),
+                    .app_data(web::Data::new(http_client.clone()))
+                    .app_data(web::Data::new(exchanges))
+                    .app_data(web::Data::from(symbol_cache.clone())),
             )
             .wrap(middleware::Logger::default())
     })
@@ -97,28 +115,62 @@ pub async fn start(server_credentials: Option<Credentials>) -> std::io::Result<(
 #[serde(untagged)]
 enum Field {
     Timestamp(i64),
-    Price(f64),
+    /// Serialized as a string with the decimal places it was read with.
+    Price(Decimal),
     Side(TradeSide),
+    Volume(Decimal),
+    /// Traded amount of the base asset.
+    Quantity(Decimal),
+    /// Traded amount of the quote asset.
+    QuoteQuantity(Decimal),
 }
 
 #[derive(Debug, Error)]
 enum DatasetError {
     #[error("Could not parse date: {0}")]
     ParseDate(chrono::ParseError),
+    #[error("Date range must span from 1 to {MAX_RANGE_DAYS} days")]
+    InvalidRange,
+    #[error("Dates must lie in the years {FIRST_YEAR} to {LAST_YEAR}")]
+    DateOutOfRange,
     #[error("Could not parse coin pair")]
     ParseCoinPair,
-    #[error("Dataset for given parameters not found")]
-    NotFound,
-    #[error("Zip reader error: {0}")]
-    HttpZipReader(HttpZipReaderError),
+    #[error("Unknown exchange {0}")]
+    UnknownExchange(String),
+    #[error("{0}")]
+    Exchange(ExchangeError),
+    #[error("{0}")]
+    Read(TradeReaderError),
 }
 
 impl ResponseError for DatasetError {
     fn status_code(&self) -> StatusCode {
         match *self {
-            DatasetError::ParseDate(_) | DatasetError::ParseCoinPair => StatusCode::BAD_REQUEST,
-            DatasetError::NotFound => StatusCode::NOT_FOUND,
-            DatasetError::HttpZipReader(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            DatasetError::ParseDate(_)
+            | DatasetError::InvalidRange
+            | DatasetError::DateOutOfRange
+            | DatasetError::ParseCoinPair => StatusCode::BAD_REQUEST,
+            DatasetError::UnknownExchange(_)
+            | DatasetError::Read(TradeReaderError::HttpZipReader(
+                _,
+                HttpZipReaderError::NotFound,
+            )) => StatusCode::NOT_FOUND,
+            DatasetError::Read(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            DatasetError::Exchange(ref err) => match err {
+                ExchangeError::SpotContract
+                | ExchangeError::UnsupportedMarket(_, _)
+                | ExchangeError::UnsupportedDataset(_, _)
+                | ExchangeError::UnsupportedContract(_, _)
+                | ExchangeError::MarketMismatch(_, _)
+                | ExchangeError::UnsupportedListing(_)
+                | ExchangeError::UnsupportedCandles(_)
+                | ExchangeError::UnsupportedRange(_, _)
+                | ExchangeError::DateOutOfRange(_) => StatusCode::BAD_REQUEST,
+                ExchangeError::NotFound => StatusCode::NOT_FOUND,
+                ExchangeError::HttpZipReader(_)
+                | ExchangeError::Page(_)
+                | ExchangeError::Listing(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
         }
     }
 
@@ -137,101 +189,406 @@ impl ResponseError for DatasetError {
     }
 }
 
-const BINANCE_SAMPLE_TRADE_EVERY: usize = 50;
-const OLX_SAMPLE_TRADE_EVERY: usize = 10;
+const MAX_RANGE_DAYS: i64 = 366;
+/// Years datasets can be requested for, far from the limits of date
+/// arithmetic.
+const FIRST_YEAR: i32 = 2000;
+const LAST_YEAR: i32 = 2100;
+
+fn check_year(year: i32) -> Result<(), DatasetError> {
+    match (FIRST_YEAR..=LAST_YEAR).contains(&year) {
+        true => Ok(()),
+        false => Err(DatasetError::DateOutOfRange),
+    }
+}
+
+fn parse_date(raw: &str) -> Result<NaiveDate, DatasetError> {
+    let date = NaiveDate::parse_from_str(raw, "%Y-%m-%d").map_err(DatasetError::ParseDate)?;
+    check_year(date.year())?;
+    Ok(date)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExchangeInfo {
+    name: &'static str,
+    markets: &'static [Market],
+    datasets: &'static [DatasetType],
+    sample_trade_every: usize,
+    candles: bool,
+}
+
+/// Lists registered exchanges with what they support, for the frontend selects.
+#[get("/exchanges")]
+async fn view_exchanges(exchanges: web::Data<ExchangeRegistry>) -> HttpResponse {
+    let exchanges = exchanges
+        .iter()
+        .map(|exchange| ExchangeInfo {
+            name: exchange.name(),
+            markets: exchange.markets(),
+            datasets: exchange.dataset_types(),
+            sample_trade_every: exchange.sample_trade_every(),
+            candles: exchange.supports_candles(),
+        })
+        .collect::<Vec<_>>();
+    HttpResponse::Ok().json(exchanges)
+}
+
+#[derive(serde::Deserialize)]
+struct SymbolsQuery {
+    market: Option<Market>,
+}
+
+/// Lists pairs available on a market as `["BTC-USDT", ...]`, for autocomplete.
+#[get("/symbols/{exchange}")]
+async fn view_symbols(
+    exchanges: web::Data<ExchangeRegistry>,
+    symbol_cache: web::Data<SymbolCache>,
+    client: web::Data<ArchiveClient>,
+    path: web::Path<String>,
+    query: web::Query<SymbolsQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let exchange_name = path.into_inner();
+    let exchange = exchanges
+        .get(&exchange_name)
+        .ok_or(DatasetError::UnknownExchange(exchange_name))?;
+    let market = listed_market(exchange, query.market)?;
+    let symbols = symbol_cache
+        .into_inner()
+        .symbols(exchange, &client, market)
+        .await
+        .map_err(DatasetError::Exchange)?;
+    Ok(HttpResponse::Ok().json(&*symbols))
+}
+
+#[derive(serde::Deserialize)]
+struct AvailabilityQuery {
+    market: Option<Market>,
+    #[serde(default)]
+    dataset: DatasetType,
+}
+
+/// Lists `[{"date": "2022-09-13", "size": 1518388}, ...]` for every day with
+/// an archive, so the date picker can be limited and big days flagged.
+#[get("/availability/{exchange}/{coin_pair}")]
+async fn view_availability(
+    exchanges: web::Data<ExchangeRegistry>,
+    client: web::Data<ArchiveClient>,
+    path: web::Path<(String, String)>,
+    query: web::Query<AvailabilityQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (exchange_name, raw_coin_pair) = path.into_inner();
+    let exchange = exchanges
+        .get(&exchange_name)
+        .ok_or(DatasetError::UnknownExchange(exchange_name))?;
+    let coin_pair: TradePair = raw_coin_pair
+        .parse()
+        .map_err(|_| DatasetError::ParseCoinPair)?;
+    let market = listed_market(exchange, query.market)?;
+    if !exchange.dataset_types().contains(&query.dataset) {
+        return Err(DatasetError::Exchange(ExchangeError::UnsupportedDataset(
+            query.dataset,
+            exchange.name().to_string(),
+        ))
+        .into());
+    }
+    let days = exchange
+        .availability(&client, market, query.dataset, coin_pair)
+        .await
+        .map_err(DatasetError::Exchange)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=3600"))
+        .json(days))
+}
+
+fn listed_market(exchange: &dyn Exchange, market: Option<Market>) -> Result<Market, DatasetError> {
+    let market = market.unwrap_or(exchange.markets()[0]);
+    if !exchange.markets().contains(&market) {
+        return Err(DatasetError::Exchange(ExchangeError::UnsupportedMarket(
+            market,
+            exchange.name().to_string(),
+        )));
+    }
+    Ok(market)
+}
+
+#[derive(serde::Deserialize)]
+struct DatasetQuery {
+    market: Option<Market>,
+    #[serde(default)]
+    dataset: DatasetType,
+    /// Last day (inclusive) when requesting a date range instead of a single day.
+    to: Option<String>,
+}
 
+/// Streams `[timestamp, price, side, quantity, quote_quantity]` rows for UTC
+/// days `date..=to`.
 #[get("/dataset/{exchange}/{coin_pair}/{date}")]
 async fn view_detailed_dataset(
-    client: web::Data<awc::Client>,
+    client: web::Data<ArchiveClient>,
+    exchanges: web::Data<ExchangeRegistry>,
     path: web::Path<(String, String, String)>,
+    query: web::Query<DatasetQuery>,
 ) -> actix_web::Result<HttpResponse> {
-    let (exchange, raw_coin_pair, raw_date) = path.into_inner();
-    let date = NaiveDate::parse_from_str(&raw_date, "%Y-%m-%d").map_err(DatasetError::ParseDate)?;
+    let (exchange_name, raw_coin_pair, raw_date) = path.into_inner();
+    let date = parse_date(&raw_date)?;
+    let to = match &query.to {
+        Some(raw_to) => parse_date(raw_to)?,
+        None => date,
+    };
+    let days = (to - date).num_days() + 1;
+    if !(1..=MAX_RANGE_DAYS).contains(&days) {
+        return Err(DatasetError::InvalidRange.into());
+    }
+    // keep the amount of points for the browser roughly the same as for a single day
+    let sample_scale = days as usize;
     let coin_pair: TradePair = raw_coin_pair
         .parse()
         .map_err(|_| DatasetError::ParseCoinPair)?;
 
-    let http_to_dataset_err = |err| match err {
-        HttpZipReaderError::NotFound => DatasetError::NotFound,
-        other => DatasetError::HttpZipReader(other),
-    };
-    let trade_reader = match &exchange[..] {
-        "binance" => {
-            binance::data::aws_trade_reader(&client, &coin_pair, date)
-                .await
-                .map_err(http_to_dataset_err)?
-                .stream(BINANCE_SAMPLE_TRADE_EVERY)
-                .await
-        }
-        "olx" => {
-            olx::data::archived_trade_reader(&client, &coin_pair, date)
-                .await
-                .map_err(http_to_dataset_err)?
-                .stream(OLX_SAMPLE_TRADE_EVERY)
-                .await
-        }
-        _ => todo!(),
+    let exchange = exchanges
+        .get(&exchange_name)
+        .ok_or(DatasetError::UnknownExchange(exchange_name))?;
+    let request = DatasetRequest {
+        trade_pair: coin_pair,
+        market: query.market,
+        dataset_type: query.dataset,
+        from: date,
+        to,
     };
+    exchange
+        .validate(&request)
+        .map_err(DatasetError::Exchange)?;
+    let trade_reader = exchange
+        .trades(
+            &client,
+            &request,
+            exchange.sample_trade_every() * sample_scale,
+        )
+        .await
+        .map_err(DatasetError::Exchange)?;
 
     debug!(
-        "Opening trade reader for {} at {} from {}",
-        coin_pair, raw_date, exchange
+        "Opening trade reader for {} at {}..={} from {}",
+        request.trade_pair,
+        date,
+        to,
+        exchange.name()
     );
+    Ok(stream_json_rows(trade_reader, serialize_trade).await?)
+}
 
-    let (mut to_write, to_read) = tokio::io::duplex(32767);
-    let _join_handle: JoinHandle<Result<(), std::io::Error>> = spawn_local(async move {
-        debug!("Draining trades essa.");
-        match trade_reader {
-            Ok(trade_stream) => {
-                let trade_stream = trade_stream.filter_map(handle_trade_error);
-                write_trades(trade_stream, to_write).await?;
-            }
-            Err(err) => match serde_json::to_vec(&json!({ "err": err.to_string() })) {
-                Ok(err_serialized) => to_write.write_all(&err_serialized).await?,
-                Err(serialize_err) => {
-                    error!("Could not serialize trade stream error: {}!", serialize_err)
-                }
-            },
-        };
-        Ok(())
-    });
+#[derive(serde::Deserialize)]
+struct CandlesQuery {
+    market: Option<Market>,
+    #[serde(default)]
+    interval: KlineInterval,
+}
+
+/// Streams `[open_time, open, high, low, close, volume]` rows for a `YYYY-MM-DD`
+/// day or a `YYYY-MM` month.
+#[get("/candles/{exchange}/{coin_pair}/{period}")]
+async fn view_candles(
+    client: web::Data<ArchiveClient>,
+    exchanges: web::Data<ExchangeRegistry>,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<CandlesQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (exchange_name, raw_coin_pair, raw_period) = path.into_inner();
+    let period: ArchivePeriod = raw_period.parse().map_err(DatasetError::ParseDate)?;
+    check_year(match period {
+        ArchivePeriod::Daily(date) => date.year(),
+        ArchivePeriod::Monthly { year, .. } => year,
+    })?;
+    let coin_pair: TradePair = raw_coin_pair
+        .parse()
+        .map_err(|_| DatasetError::ParseCoinPair)?;
+    let exchange = exchanges
+        .get(&exchange_name)
+        .ok_or(DatasetError::UnknownExchange(exchange_name))?;
+    let market = listed_market(exchange, query.market)?;
+    let candle_reader = exchange
+        .candles(&client, market, query.interval, &coin_pair, period)
+        .await
+        .map_err(DatasetError::Exchange)?;
+
+    debug!(
+        "Opening candle reader for {} at {} from {}",
+        coin_pair,
+        raw_period,
+        exchange.name()
+    );
+    Ok(stream_json_rows(candle_reader, serialize_candle).await?)
+}
+
+/// Streams rows once the first one was read, errors until then are answered
+/// with their status instead.
+async fn stream_json_rows<T: Send + 'static>(
+    rows: CreateStream<T>,
+    serialize_row: fn(T) -> serde_json::Result<Vec<u8>>,
+) -> Result<HttpResponse, DatasetError> {
+    let mut rows = rows.map_err(DatasetError::Read)?;
+    let first = loop {
+        match rows.next().await {
+            Some(Err(err)) if !is_fatal(&err) => error!("Could not read trade: {}", err),
+            Some(Err(err)) => return Err(DatasetError::Read(err)),
+            first => break first,
+        }
+    };
+    let row_stream = futures::stream::iter(first).chain(rows);
+    let (to_write, to_read) = tokio::io::duplex(32767);
+    let _join_handle: JoinHandle<Result<(), std::io::Error>> =
+        tokio::spawn(write_rows(row_stream, to_write, serialize_row));
     let stream = FramedRead::new(to_read, BytesCodec::new()).map_ok(|b| b.freeze());
 
     Ok(HttpResponse::Ok()
         .content_type("application/json")
-        .insert_header(("Cache-Control", "public, max-age=31557600"))
+        .insert_header(("Cache-Control", ROWS_CACHE_CONTROL))
         .streaming(stream))
 }
 
-fn handle_trade_error(trade_result: Result<Trade, TradeReaderError>) -> Ready<Option<Trade>> {
-    future::ready(match trade_result {
-        Ok(trade) => Some(trade),
-        Err(err) => {
-            error!("Could not read trade: {}", err);
-            None
-        }
-    })
+/// Headers are sent after the first row, so a response still ending in an
+/// error must not be kept for long.
+const ROWS_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Rows with errors are skipped, other errors end the rows, e.g. an archive
+/// missing from the range or not matching its checksum once it was read.
+fn is_fatal(err: &TradeReaderError) -> bool {
+    !matches!(err, TradeReaderError::Parse(_))
+}
+
+fn serialize_trade(trade: Trade) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec(&[
+        Field::Timestamp(trade.timestamp.as_millis()),
+        Field::Price(trade.price),
+        Field::Side(trade.side),
+        Field::Quantity(trade.quantity),
+        Field::QuoteQuantity(trade.quote_quantity),
+    ])
 }
 
-async fn write_trades(
-    mut trade_stream: impl Stream<Item = Trade> + Sized + Unpin,
+fn serialize_candle(candle: Candle) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec(&[
+        Field::Timestamp(candle.open_time.as_millis()),
+        Field::Price(candle.open),
+        Field::Price(candle.high),
+        Field::Price(candle.low),
+        Field::Price(candle.close),
+        Field::Volume(candle.volume),
+    ])
+}
+
+/// Writes rows as a JSON array. A fatal error after the response started is
+/// written as a last `{"err": ...}` element, so clients can tell the rows are
+/// incomplete.
+async fn write_rows<T>(
+    mut row_stream: impl Stream<Item = ReadResult<T>> + Sized + Unpin,
     mut to_write: DuplexStream,
+    serialize_row: fn(T) -> serde_json::Result<Vec<u8>>,
 ) -> Result<(), std::io::Error> {
-    let serialize_trade = |trade: Trade| {
-        serde_json::to_vec(&[
-            Field::Timestamp(trade.timestamp),
-            Field::Price(trade.price),
-            Field::Side(trade.side),
-        ])
-    };
     to_write.write_all("[".as_bytes()).await?;
-    if let Some(trade) = trade_stream.next().await {
-        to_write.write_all(&serialize_trade(trade)?).await?;
-    }
-    while let Some(trade) = trade_stream.next().await {
-        to_write.write_all(",".as_bytes()).await?;
-        to_write.write_all(&serialize_trade(trade)?).await?;
+    let mut separator = "";
+    while let Some(read_result) = row_stream.next().await {
+        let (element, last) = match read_result {
+            Ok(row) => (serialize_row(row)?, false),
+            Err(err) if !is_fatal(&err) => {
+                error!("Could not read trade: {}", err);
+                continue;
+            }
+            Err(err) => {
+                error!("Stopped reading trades: {}", err);
+                (
+                    serde_json::to_vec(&json!({ "err": err.to_string() }))?,
+                    true,
+                )
+            }
+        };
+        to_write.write_all(separator.as_bytes()).await?;
+        to_write.write_all(&element).await?;
+        separator = ",";
+        if last {
+            break;
+        }
     }
     to_write.write_all("]".as_bytes()).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::trade_reader::ParseTradeError;
+    use tokio::io::AsyncReadExt;
+
+    #[actix_web::test]
+    async fn end_rows_with_fatal_error() {
+        let rows = futures::stream::iter(vec![
+            Ok(1),
+            Err(TradeReaderError::Parse(ParseTradeError::MissingTradeId)),
+            Ok(2),
+            Err(TradeReaderError::IO(std::io::Error::other("corrupt"))),
+            Ok(3),
+        ]);
+        let (to_write, mut to_read) = tokio::io::duplex(1024);
+        write_rows(rows, to_write, |row: i32| serde_json::to_vec(&row))
+            .await
+            .unwrap();
+
+        let mut written = String::new();
+        to_read.read_to_string(&mut written).await.unwrap();
+        assert_eq!(written, r#"[1,2,{"err":"IO error: corrupt"}]"#);
+    }
+
+    #[actix_web::test]
+    async fn fail_before_first_row() {
+        let missing = |url: &str| {
+            TradeReaderError::HttpZipReader(url.to_string(), HttpZipReaderError::NotFound)
+        };
+        let failed: CreateStream<i32> = Err(missing("https://data.example.com/a.zip"));
+        let err = stream_json_rows(failed, |row| serde_json::to_vec(&row))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+
+        let rows: Vec<ReadResult<i32>> = vec![
+            Err(TradeReaderError::Parse(ParseTradeError::MissingTradeId)),
+            Err(TradeReaderError::IO(std::io::Error::other("corrupt"))),
+            Ok(1),
+        ];
+        let err = stream_json_rows(Ok(Box::pin(futures::stream::iter(rows))), |row| {
+            serde_json::to_vec(&row)
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_web::test]
+    async fn report_missing_day_of_range() {
+        let rows: Vec<ReadResult<i32>> = vec![
+            Ok(1),
+            Err(TradeReaderError::HttpZipReader(
+                "https://data.example.com/2022-09-14.zip".to_string(),
+                HttpZipReaderError::NotFound,
+            )),
+            Ok(3),
+        ];
+        let response = stream_json_rows(Ok(Box::pin(futures::stream::iter(rows))), |row| {
+            serde_json::to_vec(&row)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response.headers().get("Cache-Control").unwrap(),
+            ROWS_CACHE_CONTROL
+        );
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            r#"[1,{"err":"Could not open https://data.example.com/2022-09-14.zip: Trades for given params are not present."}]"#
+        );
+    }
+}